@@ -0,0 +1,20 @@
+use std::io::{self, Cursor};
+
+use rgssad::{ArchiveBuilder, RGSSArchive};
+
+fn main() -> io::Result<()> {
+    let mut builder = ArchiveBuilder::new(1);
+    builder
+        .add_entry("Data/Scripts.rxdata", &b"scripts"[..])?
+        .add_entry("Graphics/Titles/title.png", &b"title"[..])?;
+    let mut data = Vec::new();
+    builder.write(&mut data)?;
+
+    let mut cursor = Cursor::new(&data);
+    let archive = RGSSArchive::open(&mut cursor)?;
+    for entry in &archive.entries {
+        let body = entry.extract_to_vec(&data)?;
+        println!("{}: {}", entry.name, String::from_utf8_lossy(&body));
+    }
+    Ok(())
+}
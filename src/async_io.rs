@@ -0,0 +1,86 @@
+use std::io::{self, SeekFrom};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{Keystream, RGSSArchiveEntry, RgssError, E_BUFFER_TOO_SMALL, E_SIZE_MISMATCH};
+
+/// The async counterpart of `run_codec`. Chunks are split the same way, so
+/// for the same input both produce byte-identical output.
+async fn run_codec_async(
+    buf: &mut [u8],
+    input: &mut (impl AsyncRead + Unpin),
+    output: &mut (impl AsyncWrite + Unpin),
+    mut size: u32,
+    magic: u32,
+) -> io::Result<u64> {
+    let limit = buf.len() - buf.len() % 4;
+    if limit == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            E_BUFFER_TOO_SMALL,
+        ));
+    }
+    let mut keystream = Keystream::new(magic);
+    let mut done = 0;
+    loop {
+        let buf = &mut buf[..limit.min(size as usize)];
+        // Fill the whole chunk unless the input ends, as `ReadFull` does.
+        let mut read = 0;
+        while read < buf.len() {
+            match input.read(&mut buf[read..]).await {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if read == 0 {
+            break;
+        }
+        let buf = &mut buf[..read];
+        keystream.apply(buf);
+        size -= read as u32;
+        output.write_all(buf).await?;
+        done += read as u64;
+    }
+    Ok(done)
+}
+
+impl RGSSArchiveEntry {
+    /// Like [`read`](Self::read), for async readers and writers.
+    pub async fn read_async(
+        &self,
+        buf: &mut [u8],
+        r: &mut (impl AsyncRead + AsyncSeek + Unpin),
+        w: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<(), RgssError> {
+        // Checked as in `seek_body`: seeking past the end succeeds, and the
+        // body would then read as empty.
+        let len = r.seek(SeekFrom::End(0)).await?;
+        if self.offset as u64 + self.size as u64 > len {
+            return Err(RgssError::TruncatedEntry);
+        }
+        r.seek(SeekFrom::Start(self.offset as u64)).await?;
+        run_codec_async(buf, r, w, self.size, self.magic).await?;
+        Ok(())
+    }
+
+    /// Like [`write`](Self::write), for async readers and writers. A source
+    /// that is shorter or longer than `size` is rejected the same way.
+    pub async fn write_async(
+        &self,
+        buf: &mut [u8],
+        w: &mut (impl AsyncWrite + AsyncSeek + Unpin),
+        r: &mut (impl AsyncRead + Unpin),
+    ) -> io::Result<()> {
+        w.seek(SeekFrom::Start(self.offset as u64)).await?;
+        let written = run_codec_async(buf, r, w, self.size, self.magic).await?;
+        if written < self.size as u64 || r.read(&mut [0]).await? != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", E_SIZE_MISMATCH, self.name),
+            ));
+        }
+        Ok(())
+    }
+}
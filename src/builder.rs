@@ -0,0 +1,63 @@
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+
+use crate::{NameEncoding, RGSSArchive, RGSSArchiveEntry, E_ENTRY_TOO_LARGE};
+
+/// Collects entries from memory and writes them out as an archive, for callers
+/// that have no directory to pack.
+#[derive(Clone, Debug, Default)]
+pub struct ArchiveBuilder {
+    archive: RGSSArchive,
+    bodies: Vec<Vec<u8>>,
+}
+
+impl ArchiveBuilder {
+    pub fn new(version: u8) -> Self {
+        ArchiveBuilder {
+            archive: RGSSArchive {
+                version,
+                ..RGSSArchive::default()
+            },
+            bodies: Vec::new(),
+        }
+    }
+
+    /// Sets the base key of a v3 archive, as [`RGSSArchive::with_magic`] does.
+    pub fn with_magic(mut self, magic: u32) -> Self {
+        self.archive.magic = magic;
+        self
+    }
+
+    pub fn with_encoding(mut self, encoding: NameEncoding) -> Self {
+        self.archive.encoding = encoding;
+        self
+    }
+
+    /// Reads `data` to the end and queues it under `name`, which is added with
+    /// [`RGSSArchive::push_entry`]: `\` is taken as `/`, and a name that was
+    /// already added is an error.
+    pub fn add_entry(&mut self, name: &str, mut data: impl Read) -> io::Result<&mut Self> {
+        let mut body = Vec::new();
+        data.read_to_end(&mut body)?;
+        let size = body
+            .len()
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, E_ENTRY_TOO_LARGE))?;
+        self.archive.push_entry(RGSSArchiveEntry {
+            name: name.to_owned(),
+            size,
+            ..RGSSArchiveEntry::default()
+        })?;
+        self.bodies.push(body);
+        Ok(self)
+    }
+
+    /// Writes the archive and returns its table, with the offsets and keys
+    /// that were assigned.
+    pub fn write(mut self, w: &mut impl Write) -> io::Result<RGSSArchive> {
+        let mut bodies = self.bodies.iter();
+        self.archive
+            .write_to(&mut vec![0; 8192], w, |_| Ok(&bodies.next().unwrap()[..]))?;
+        Ok(self.archive)
+    }
+}
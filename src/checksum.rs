@@ -0,0 +1,28 @@
+use std::io::{self, Read, Seek, Write};
+
+use crc32fast::Hasher;
+
+use crate::{RGSSArchiveEntry, RgssError};
+
+struct CrcWriter(Hasher);
+
+impl Write for CrcWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl RGSSArchiveEntry {
+    /// Computes the CRC-32 of the decrypted body, as used by zip and gzip,
+    /// without writing the body anywhere.
+    pub fn checksum(&self, buf: &mut [u8], r: &mut (impl Read + Seek)) -> Result<u32, RgssError> {
+        let mut crc = CrcWriter(Hasher::new());
+        self.read(buf, r, &mut crc)?;
+        Ok(crc.0.finalize())
+    }
+}
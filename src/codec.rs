@@ -0,0 +1,43 @@
+use std::io::{self, Read};
+
+use crate::Keystream;
+
+/// Decrypts an entry body while reading it. The keystream starts at `magic`
+/// and advances every four bytes, exactly as [`RGSSArchiveEntry::read`]
+/// does, so reads of any length produce the same output.
+///
+/// [`RGSSArchiveEntry::read`]: crate::RGSSArchiveEntry::read
+#[derive(Debug)]
+pub struct DecryptReader<R> {
+    inner: R,
+    remaining: u64,
+    keystream: Keystream,
+}
+
+impl<R: Read> DecryptReader<R> {
+    /// Wraps `inner`, which must be positioned at the start of the body.
+    /// At most `size` bytes are read from it.
+    pub fn new(inner: R, size: u32, magic: u32) -> Self {
+        DecryptReader {
+            inner,
+            remaining: size as u64,
+            keystream: Keystream::new(magic),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let limit = buf
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        let read = self.inner.read(&mut buf[..limit])?;
+        self.keystream.apply(&mut buf[..read]);
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
@@ -0,0 +1,133 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::NameEncoding;
+
+const E_INVALID_HEADER: &str = "Invalid header";
+const E_UNSUPPORTED_VERSION: &str = "Unsupported version";
+const E_INVALID_NAME: &str = "Entry name is not valid";
+const E_TRUNCATED_ENTRY: &str = "Truncated entry";
+const E_NAME_TOO_LONG: &str = "Entry name too long";
+const E_UNSAFE_NAME: &str = "Entry name is not a safe relative path";
+const E_DUPLICATE_NAMES: &str = "Duplicate entry names";
+const E_TOO_MANY_ENTRIES: &str = "Too many entries";
+const E_OFFSET_OVERFLOW: &str = "Archive exceeds the 4 GiB offset limit at entry";
+
+#[derive(Debug)]
+pub enum RgssError {
+    Io(io::Error),
+    /// The file does not start with `RGSSAD\0`. Holds the 8 bytes found
+    /// instead.
+    InvalidHeader([u8; 8]),
+    UnsupportedVersion(u8),
+    /// A name could not be converted. When reading, `offset` is the position
+    /// of the table record holding it.
+    InvalidName {
+        encoding: NameEncoding,
+        offset: Option<u64>,
+    },
+    TruncatedEntry,
+    NameTooLong(u32),
+    /// The name cannot be extracted; see [`crate::sanitize_name`]. It is
+    /// shown escaped, since it may hold control characters.
+    UnsafeName(String),
+    DuplicateNames(Vec<String>),
+    /// The table holds more entries than the configured limit.
+    TooManyEntries(usize),
+    /// The archive being written or read extends past what 32-bit offsets can
+    /// address. Holds the name of the entry that does not fit.
+    OffsetOverflow(String),
+}
+
+impl RgssError {
+    /// Records where in the table an invalid name was found.
+    pub(crate) fn at(self, position: u64) -> Self {
+        match self {
+            RgssError::InvalidName { encoding, .. } => RgssError::InvalidName {
+                encoding,
+                offset: Some(position),
+            },
+            e => e,
+        }
+    }
+
+    pub(crate) fn truncated(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => RgssError::TruncatedEntry,
+            _ => RgssError::Io(e),
+        }
+    }
+}
+
+impl fmt::Display for RgssError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RgssError::Io(e) => e.fmt(f),
+            RgssError::InvalidHeader(found) => {
+                let at = b"RGSSAD\0"
+                    .iter()
+                    .zip(found)
+                    .position(|(expected, found)| expected != found)
+                    .unwrap_or(0);
+                write!(
+                    f,
+                    "{}: expected \"RGSSAD\\0\", found \"{}\" (mismatch at byte {})",
+                    E_INVALID_HEADER,
+                    found.escape_ascii(),
+                    at
+                )
+            }
+            RgssError::UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "{}: {} (expected 1, 2 or 3)",
+                    E_UNSUPPORTED_VERSION, version
+                )
+            }
+            RgssError::InvalidName { encoding, offset } => {
+                write!(f, "{} {}", E_INVALID_NAME, encoding)?;
+                if let Some(offset) = offset {
+                    write!(f, " (record at offset {})", offset)?;
+                }
+                Ok(())
+            }
+            RgssError::TruncatedEntry => f.write_str(E_TRUNCATED_ENTRY),
+            RgssError::NameTooLong(len) => write!(f, "{}: {} bytes", E_NAME_TOO_LONG, len),
+            RgssError::UnsafeName(name) => {
+                write!(f, "{}: {}", E_UNSAFE_NAME, name.escape_debug())
+            }
+            RgssError::TooManyEntries(limit) => {
+                write!(f, "{}: more than {}", E_TOO_MANY_ENTRIES, limit)
+            }
+            RgssError::OffsetOverflow(name) => write!(f, "{}: {}", E_OFFSET_OVERFLOW, name),
+            RgssError::DuplicateNames(names) => {
+                write!(f, "{}: {}", E_DUPLICATE_NAMES, names.join(", "))
+            }
+        }
+    }
+}
+
+impl Error for RgssError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RgssError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RgssError {
+    fn from(e: io::Error) -> Self {
+        RgssError::Io(e)
+    }
+}
+
+impl From<RgssError> for io::Error {
+    fn from(e: RgssError) -> Self {
+        match e {
+            RgssError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
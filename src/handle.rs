@@ -0,0 +1,66 @@
+use std::io::{Read, Seek};
+
+use crate::{RGSSArchive, RGSSArchiveEntry, RgssError};
+
+/// An entry together with the source it was read from, so that its body can
+/// be read without passing the source around. Created by
+/// [`RGSSArchive::entry`] and [`RGSSArchive::handles`].
+///
+/// The source is only borrowed, which works for sources that can be read
+/// through a shared reference, such as `&File`.
+#[derive(Debug)]
+pub struct EntryHandle<'a, R> {
+    pub entry: &'a RGSSArchiveEntry,
+    source: &'a R,
+}
+
+impl<'a, R> EntryHandle<'a, R>
+where
+    &'a R: Read + Seek,
+{
+    /// Reads and decrypts the whole body.
+    ///
+    /// ```
+    /// use std::fs::{self, File};
+    ///
+    /// use rgssad::{ArchiveBuilder, RGSSArchive};
+    ///
+    /// let path = std::env::temp_dir().join(format!("handle-{}.rgssad", std::process::id()));
+    /// let mut builder = ArchiveBuilder::new(1);
+    /// builder.add_entry("Data/Scripts.rxdata", &b"scripts"[..])?;
+    /// builder.write(&mut File::create(&path)?)?;
+    ///
+    /// let file = File::open(&path)?;
+    /// let archive = RGSSArchive::open(&mut &file)?;
+    /// let handle = archive.entry(&file, "Data/Scripts.rxdata").unwrap();
+    /// assert_eq!(handle.bytes()?, b"scripts");
+    /// # fs::remove_file(&path)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn bytes(&self) -> Result<Vec<u8>, RgssError> {
+        let mut out = Vec::with_capacity(self.entry.size as usize);
+        self.entry.reader(self.source)?.read_to_end(&mut out)?;
+        if out.len() < self.entry.size as usize {
+            return Err(RgssError::TruncatedEntry);
+        }
+        Ok(out)
+    }
+}
+
+impl RGSSArchive {
+    /// Looks up an entry by name, as [`find`](Self::find) does, pairing it
+    /// with `source`, which must be the archive the table was read from.
+    pub fn entry<'a, R>(&'a self, source: &'a R, name: &str) -> Option<EntryHandle<'a, R>> {
+        self.find(name).map(|entry| EntryHandle { entry, source })
+    }
+
+    /// Pairs every entry with `source`, in table order.
+    pub fn handles<'a, R>(
+        &'a self,
+        source: &'a R,
+    ) -> impl Iterator<Item = EntryHandle<'a, R>> + 'a {
+        self.entries
+            .iter()
+            .map(move |entry| EntryHandle { entry, source })
+    }
+}
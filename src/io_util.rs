@@ -20,6 +20,7 @@ impl<R: Read> ReadFull for R {
 
 pub trait ReadNum {
     fn read_u32_le(&mut self) -> io::Result<u32>;
+    fn read_u32_le_or_eof(&mut self) -> io::Result<Option<u32>>;
 }
 
 impl<R: Read> ReadNum for R {
@@ -28,6 +29,15 @@ impl<R: Read> ReadNum for R {
         self.read_exact(&mut buf)?;
         Ok(u32::from_le_bytes(buf))
     }
+
+    fn read_u32_le_or_eof(&mut self) -> io::Result<Option<u32>> {
+        let mut buf = [0; 4];
+        match self.read_full(&mut buf)? {
+            0 => Ok(None),
+            4 => Ok(Some(u32::from_le_bytes(buf))),
+            _ => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
 }
 
 pub trait WriteNum {
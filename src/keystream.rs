@@ -0,0 +1,170 @@
+/// The key that v1/v2 tables start from, the initial magic of the table
+/// keystream. The name lengths, names and sizes of the records are all
+/// encrypted with one keystream seeded with it, and each body is encrypted
+/// with the key that stream has reached at its start. Every reader and writer
+/// of v1/v2 tables, including `RGSSArchive::append`, starts from this one
+/// constant.
+pub const RGSSAD_INITIAL_MAGIC: u32 = 0xdeadcafe;
+
+/// The former name of [`RGSSAD_INITIAL_MAGIC`].
+#[deprecated(note = "use RGSSAD_INITIAL_MAGIC")]
+pub const RGSSAD_SEED: u32 = RGSSAD_INITIAL_MAGIC;
+
+/// Returns the key that follows `magic`, `magic * 7 + 3` with wrapping
+/// arithmetic. Every keystream in the format steps its key this way.
+pub fn next_magic(magic: u32) -> u32 {
+    magic.wrapping_mul(7).wrapping_add(3)
+}
+
+/// Returns the successive keys of a v1/v2 table keystream, starting with
+/// `seed` itself, which is [`RGSSAD_INITIAL_MAGIC`] in real archives. One
+/// key is used per name length, per name byte and per size. From
+/// [`RGSSAD_INITIAL_MAGIC`] the keys run `0xdeadcafe`, `0x16c08cf5`,
+/// `0x9f43dab6`, `0x5adafafd`, ...
+pub fn rgssad_name_keystream(seed: u32) -> impl Iterator<Item = u32> {
+    core::iter::successors(Some(seed), |&magic| Some(next_magic(magic)))
+}
+
+/// Returns the word that every field of a v3 table is XORed with, derived from
+/// the archive key stored right after the header as `magic * 9 + 3` with
+/// wrapping arithmetic.
+pub fn rgss3a_xor(magic: u32) -> u32 {
+    magic.wrapping_mul(9).wrapping_add(3)
+}
+
+/// Steps a key to the next one, returning the key before the step.
+pub(crate) fn advance_magic(magic: &mut u32) -> u32 {
+    core::mem::replace(magic, next_magic(*magic))
+}
+
+/// The keystream that entry bodies are encrypted with. Every 4-byte word is
+/// XORed with the little-endian bytes of the current key, which then steps to
+/// the next one; a trailing partial word uses the key without stepping it.
+///
+/// This only needs `core`, so it is available without the `std` feature.
+#[derive(Clone, Debug)]
+pub struct Keystream {
+    magic: u32,
+    pos: usize,
+}
+
+impl Keystream {
+    /// Starts the keystream of a body whose key is `magic`.
+    pub fn new(magic: u32) -> Self {
+        Keystream { magic, pos: 0 }
+    }
+
+    /// Returns the key for the next whole word and steps past it. The stream
+    /// must be at a word boundary, i.e. [`apply`](Self::apply) must only have
+    /// been given whole words so far.
+    pub fn next_word(&mut self) -> u32 {
+        debug_assert_eq!(self.pos, 0);
+        advance_magic(&mut self.magic)
+    }
+
+    /// XORs `buf` with the next `buf.len()` bytes of the keystream. Splitting a
+    /// body into calls of any length gives the same result as a single call.
+    pub fn apply(&mut self, buf: &mut [u8]) {
+        let mut buf = buf;
+        while self.pos != 0 && !buf.is_empty() {
+            let (b, rest) = buf.split_first_mut().unwrap();
+            self.apply_byte(b);
+            buf = rest;
+        }
+        let mut words = buf.chunks_exact_mut(4);
+        for word in &mut words {
+            let key = self.next_word().to_le_bytes();
+            for (b, k) in word.iter_mut().zip(key) {
+                *b ^= k;
+            }
+        }
+        for b in words.into_remainder() {
+            self.apply_byte(b);
+        }
+    }
+
+    fn apply_byte(&mut self, b: &mut u8) {
+        *b ^= self.magic.to_le_bytes()[self.pos];
+        self.pos += 1;
+        if self.pos == 4 {
+            advance_magic(&mut self.magic);
+            self.pos = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgssad_keys_are_pinned() {
+        let mut keys = [0; 6];
+        for (key, magic) in keys
+            .iter_mut()
+            .zip(rgssad_name_keystream(RGSSAD_INITIAL_MAGIC))
+        {
+            *key = magic;
+        }
+        assert_eq!(
+            keys,
+            [0xdeadcafe, 0x16c08cf5, 0x9f43dab6, 0x5adafafd, 0x7bfcdcee, 0x63ea0a85]
+        );
+        assert_eq!(next_magic(0xffff_ffff), 0xffff_fffc);
+        let mut magic = RGSSAD_INITIAL_MAGIC;
+        assert_eq!(advance_magic(&mut magic), RGSSAD_INITIAL_MAGIC);
+        assert_eq!(magic, 0x16c08cf5);
+    }
+
+    #[test]
+    fn rgss3a_xor_is_pinned() {
+        assert_eq!(rgss3a_xor(0), 3);
+        assert_eq!(rgss3a_xor(RGSSAD_INITIAL_MAGIC), 0xd41c22f1);
+        assert_eq!(rgss3a_xor(0x1234_5678), 0xa3d70a3b);
+        assert_eq!(rgss3a_xor(0xffff_ffff), 0xffff_fffa);
+    }
+
+    #[test]
+    fn keystream_round_trips() {
+        let plain: [u8; 11] = *b"hello world";
+        let mut buf = plain;
+        Keystream::new(0x1234_5678).apply(&mut buf);
+        assert_ne!(buf, plain);
+        Keystream::new(0x1234_5678).apply(&mut buf);
+        assert_eq!(buf, plain);
+    }
+
+    #[test]
+    fn keystream_steps_once_per_word() {
+        let mut buf = [0; 9];
+        Keystream::new(1).apply(&mut buf);
+        assert_eq!(buf[..4], 1u32.to_le_bytes());
+        assert_eq!(buf[4..8], 10u32.to_le_bytes());
+        // The trailing byte uses the third key, 10 * 7 + 3.
+        assert_eq!(buf[8], 73);
+    }
+
+    #[test]
+    fn next_word_matches_apply() {
+        let mut words = Keystream::new(RGSSAD_INITIAL_MAGIC);
+        let mut buf = [0; 12];
+        Keystream::new(RGSSAD_INITIAL_MAGIC).apply(&mut buf);
+        for word in buf.chunks_exact(4) {
+            assert_eq!(word, words.next_word().to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn keystream_splits_anywhere() {
+        let mut whole = [0xa5; 23];
+        Keystream::new(RGSSAD_INITIAL_MAGIC).apply(&mut whole);
+        for split in [1, 2, 3, 5, 7] {
+            let mut parts = [0xa5; 23];
+            let mut keystream = Keystream::new(RGSSAD_INITIAL_MAGIC);
+            for chunk in parts.chunks_mut(split) {
+                keystream.apply(chunk);
+            }
+            assert_eq!(parts, whole, "chunks of {}", split);
+        }
+    }
+}
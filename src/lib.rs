@@ -1,26 +1,127 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "tokio")]
+mod async_io;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod checksum;
+#[cfg(feature = "std")]
+mod codec;
+#[cfg(feature = "std")]
+mod error;
+#[cfg(feature = "std")]
+mod handle;
+#[cfg(feature = "std")]
 mod io_util;
+mod keystream;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "std")]
+mod parallel;
+#[cfg(feature = "std")]
+mod recover;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(feature = "std")]
+mod verify;
 
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+#[cfg(feature = "std")]
+use std::cmp::Ordering;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
 use std::convert::TryInto;
-use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "std")]
+use std::fmt;
+#[cfg(feature = "std")]
+use std::fs::{self, File};
+#[cfg(feature = "std")]
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "std")]
+use std::ops::Range;
+#[cfg(feature = "std")]
+use std::path::{Component, Path, PathBuf};
+
+#[cfg(feature = "std")]
+use encoding_rs::SHIFT_JIS;
 
+#[cfg(feature = "std")]
+pub use builder::ArchiveBuilder;
+#[cfg(feature = "std")]
+pub use codec::DecryptReader;
+#[cfg(feature = "std")]
+pub use error::RgssError;
+#[cfg(feature = "std")]
+pub use handle::EntryHandle;
+#[cfg(feature = "std")]
 use io_util::{ReadFull, ReadNum, WriteNum};
+#[cfg(feature = "std")]
+use keystream::advance_magic;
+#[allow(deprecated)]
+pub use keystream::RGSSAD_SEED;
+pub use keystream::{
+    next_magic, rgss3a_xor, rgssad_name_keystream, Keystream, RGSSAD_INITIAL_MAGIC,
+};
+#[cfg(feature = "mmap")]
+pub use mmap::MappedArchive;
+#[cfg(feature = "std")]
+pub use stream::{EntryStream, StreamedEntry};
+#[cfg(feature = "std")]
+pub use verify::VerifyIssue;
 
-const E_INVALID_HEADER: &str = "Invalid header";
-const E_UNSUPPORTED_VERSION: &str = "Unsupported version";
+#[cfg(feature = "std")]
+const E_BUFFER_TOO_SMALL: &str = "Codec buffer must hold at least 4 bytes";
+#[cfg(feature = "std")]
+const E_UNALIGNED_RANGE: &str = "Range start must be a multiple of 4";
+#[cfg(feature = "std")]
+const E_RANGE_OUT_OF_BOUNDS: &str = "Range extends past the end of the entry";
+#[cfg(feature = "std")]
+const E_APPEND_UNSUPPORTED: &str = "Only v1/v2 archives can be appended to";
+#[cfg(feature = "std")]
+const E_ENTRY_TOO_LARGE: &str = "Entry body exceeds 4 GiB";
+#[cfg(feature = "std")]
+const E_ARCHIVE_TOO_LARGE: &str = "Archive exceeds 4 GiB";
+#[cfg(feature = "std")]
+const E_SHORT_BODY: &str = "Entry body ended early";
+#[cfg(feature = "std")]
+const E_SIZE_MISMATCH: &str = "Source length does not match the entry size";
 
-fn advance_magic(magic: &mut u32) -> u32 {
-    std::mem::replace(magic, magic.wrapping_mul(7).wrapping_add(3))
+/// Allocates a buffer for [`RGSSArchiveEntry::read`] and friends of at least
+/// `size` bytes, rounded up to a whole number of 4-byte words.
+#[cfg(feature = "std")]
+pub fn codec_buffer(size: usize) -> Vec<u8> {
+    vec![0; size.max(4).saturating_add(3) & !3]
 }
 
+/// Returns the number of bytes written, which is less than `size` if `input`
+/// ended early.
+///
+/// `async_io::run_codec_async` mirrors this loop and must stay byte for byte
+/// compatible with it.
+#[cfg(feature = "std")]
 fn run_codec(
     buf: &mut [u8],
     input: &mut impl Read,
     output: &mut impl Write,
     mut size: u32,
-    mut magic: u32,
-) -> io::Result<()> {
-    let limit = buf.len();
-    assert!(limit % 4 == 0);
+    magic: u32,
+    progress: &mut impl FnMut(u64),
+) -> io::Result<u64> {
+    // Every chunk but the last must hold whole words, so only use a multiple
+    // of 4 bytes of the buffer.
+    let limit = buf.len() - buf.len() % 4;
+    if limit == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            E_BUFFER_TOO_SMALL,
+        ));
+    }
+    let mut keystream = Keystream::new(magic);
+    let mut done = 0;
+    progress(done);
     loop {
         let buf = &mut buf[..limit.min(size as usize)];
         let read = input.read_full(buf)?;
@@ -28,21 +129,102 @@ fn run_codec(
             break;
         }
         let buf = &mut buf[..read];
-        let (prefix, middle, suffix) = unsafe { buf.align_to_mut::<u32>() };
-        assert!(prefix.is_empty());
-        for b in middle.iter_mut() {
-            *b ^= advance_magic(&mut magic).to_le();
-        }
-        for (i, b) in suffix.iter_mut().enumerate() {
-            *b ^= magic.to_le_bytes()[i];
-        }
+        keystream.apply(buf);
         size -= read as u32;
         output.write_all(buf)?;
+        done += read as u64;
+        progress(done);
+    }
+    Ok(done)
+}
+
+/// Reads the next v1/v2 record header, which starts at `position`, returning
+/// the entry name, the body size and the number of bytes the header occupied,
+/// or `None` at the end of the table.
+#[cfg(feature = "std")]
+fn read_record_rgssad(
+    r: &mut impl Read,
+    magic: &mut u32,
+    options: &ReadOptions,
+    position: u64,
+) -> Result<Option<(String, u32, u64)>, RgssError> {
+    let name_len = match r.read_u32_le_or_eof().map_err(RgssError::truncated)? {
+        Some(name_len) => name_len ^ advance_magic(magic),
+        None => return Ok(None),
+    };
+    let mut name = vec![0; options.check_name_len(name_len)?];
+    r.read_exact(&mut name).map_err(RgssError::truncated)?;
+    for b in name.iter_mut() {
+        *b ^= advance_magic(magic) as u8;
+    }
+    let name = options.encoding.decode(name).map_err(|e| e.at(position))?;
+    let size = r.read_u32_le().map_err(RgssError::truncated)? ^ advance_magic(magic);
+    Ok(Some((name, size, name_len as u64 + 8)))
+}
+
+/// Writes a v1/v2 record header for an already encoded name, continuing the
+/// table keystream in `magic`.
+#[cfg(feature = "std")]
+fn write_record_rgssad(
+    w: &mut impl Write,
+    magic: &mut u32,
+    mut name: Vec<u8>,
+    size: u32,
+) -> io::Result<()> {
+    let name_len: u32 = name.len().try_into().unwrap();
+    w.write_u32_le(name_len ^ advance_magic(magic))?;
+    for b in name.iter_mut() {
+        *b ^= advance_magic(magic) as u8;
     }
+    w.write_all(&name)?;
+    w.write_u32_le(size ^ advance_magic(magic))?;
     Ok(())
 }
 
+/// Creates a file for writing, along with any missing parent directories.
+#[cfg(feature = "std")]
+fn create_file(path: impl AsRef<Path>) -> io::Result<File> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    File::create(path)
+}
+
+/// Converts an entry name into a relative path that is safe to join onto an
+/// output directory. Both `/` and `\` separate components. Names that are
+/// absolute, carry a drive prefix, contain `..` components or hold a NUL byte,
+/// which no filesystem accepts, are rejected; empty and `.` components are
+/// dropped.
+#[cfg(feature = "std")]
+pub fn sanitize_name(name: &str) -> Result<PathBuf, RgssError> {
+    let unsafe_name = || RgssError::UnsafeName(name.to_owned());
+    if name.starts_with(['/', '\\']) || name.contains('\0') {
+        return Err(unsafe_name());
+    }
+    let mut path = PathBuf::new();
+    for component in name.split(['/', '\\']) {
+        if component.is_empty() || component == "." {
+            continue;
+        }
+        match Path::new(component).components().next() {
+            Some(Component::Normal(_)) if component != ".." => path.push(component),
+            _ => return Err(unsafe_name()),
+        }
+    }
+    if path.as_os_str().is_empty() {
+        return Err(unsafe_name());
+    }
+    Ok(path)
+}
+
+/// An entry of the table. The format stores offsets and sizes in 32 bits, so
+/// an archive cannot extend past 4 GiB. Positions are computed in 64 bits
+/// and checked, and writing an archive that would pass the limit fails with
+/// [`RgssError::OffsetOverflow`] instead of wrapping.
+#[cfg(feature = "std")]
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RGSSArchiveEntry {
     pub name: String,
     pub size: u32,
@@ -50,93 +232,833 @@ pub struct RGSSArchiveEntry {
     pub magic: u32,
 }
 
+/// Formats the entry as `name: { size: .., offset: .., magic: .. }`, the
+/// format of `rgssad list`.
+#[cfg(feature = "std")]
+impl fmt::Display for RGSSArchiveEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {{ size: {}, offset: {}, magic: {} }}",
+            self.name, self.size, self.offset, self.magic
+        )
+    }
+}
+
+#[cfg(feature = "std")]
 impl RGSSArchiveEntry {
+    /// Compares entries by name, treating `\` as `/`, then by offset. This is
+    /// the canonical order used for listings and diffs:
+    /// `entries.sort_by(RGSSArchiveEntry::cmp_by_name)`.
+    pub fn cmp_by_name(&self, other: &Self) -> Ordering {
+        let normalize = |c| if c == '\\' { '/' } else { c };
+        self.name
+            .chars()
+            .map(normalize)
+            .cmp(other.name.chars().map(normalize))
+            .then(self.offset.cmp(&other.offset))
+    }
+
+    /// Seeks `r` to `start` bytes into the body, after checking that the whole
+    /// body lies within the stream. Seeking past the end would otherwise
+    /// succeed and leave the body to read as empty.
+    fn seek_body(&self, r: &mut impl Seek, start: u32) -> Result<(), RgssError> {
+        let len = r.seek(SeekFrom::End(0))?;
+        if self.offset as u64 + self.size as u64 > len {
+            return Err(RgssError::TruncatedEntry);
+        }
+        r.seek(SeekFrom::Start(self.offset as u64 + start as u64))?;
+        Ok(())
+    }
+
+    /// Seeks `r` to the start of the body and returns a reader over its
+    /// decrypted contents.
+    pub fn reader<R: Read + Seek>(&self, mut r: R) -> Result<DecryptReader<R>, RgssError> {
+        self.seek_body(&mut r, 0)?;
+        Ok(DecryptReader::new(r, self.size, self.magic))
+    }
+
+    /// Decrypts the body from an archive held entirely in memory.
+    pub fn extract_to_vec(&self, data: &[u8]) -> Result<Vec<u8>, RgssError> {
+        let body = data
+            .get(self.offset as usize..)
+            .and_then(|data| data.get(..self.size as usize))
+            .ok_or(RgssError::TruncatedEntry)?;
+        let mut out = Vec::with_capacity(body.len());
+        DecryptReader::new(body, self.size, self.magic).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    /// Decrypts the body into `w`, using `buf` for each chunk. `buf` must hold
+    /// at least 4 bytes, and only its largest multiple of 4 is used, since
+    /// the keystream advances once per word; see [`codec_buffer`].
     pub fn read(
         &self,
         buf: &mut [u8],
         r: &mut (impl Read + Seek),
         w: &mut impl Write,
-    ) -> io::Result<()> {
-        r.seek(SeekFrom::Start(self.offset as u64))?;
-        run_codec(buf, r, w, self.size, self.magic)?;
+    ) -> Result<(), RgssError> {
+        self.read_with_progress(buf, r, w, |_, _, _| {})
+    }
+
+    /// Like [`read`](Self::read), but returns the number of bytes written.
+    /// Fails with [`RgssError::TruncatedEntry`] if the body extends past the
+    /// end of `r`, and with [`io::ErrorKind::UnexpectedEof`] if `r` still
+    /// runs out before the whole body has been read.
+    pub fn read_to_writer(
+        &self,
+        buf: &mut [u8],
+        r: &mut (impl Read + Seek),
+        w: &mut impl Write,
+    ) -> Result<u64, RgssError> {
+        self.seek_body(r, 0)?;
+        let written = run_codec(buf, r, w, self.size, self.magic, &mut |_| {})?;
+        if written < self.size as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{}: {} of {} bytes", E_SHORT_BODY, written, self.size),
+            )
+            .into());
+        }
+        Ok(written)
+    }
+
+    /// Like [`read`](Self::read), calling `progress` with the entry name, the
+    /// number of bytes decrypted so far and the body size, once before the
+    /// first chunk and again after every chunk.
+    pub fn read_with_progress(
+        &self,
+        buf: &mut [u8],
+        r: &mut (impl Read + Seek),
+        w: &mut impl Write,
+        mut progress: impl FnMut(&str, u64, u64),
+    ) -> Result<(), RgssError> {
+        self.seek_body(r, 0)?;
+        run_codec(buf, r, w, self.size, self.magic, &mut |done| {
+            progress(&self.name, done, self.size as u64)
+        })?;
         Ok(())
     }
 
+    /// Decrypts `len` bytes of the body starting at `start` into `w`, without
+    /// processing the bytes before it. The keystream advances once per word,
+    /// so `start` must be a multiple of 4; `len` may be anything that stays
+    /// within the body.
+    pub fn extract_range(
+        &self,
+        buf: &mut [u8],
+        r: &mut (impl Read + Seek),
+        w: &mut impl Write,
+        start: u32,
+        len: u32,
+    ) -> Result<(), RgssError> {
+        if start % 4 != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, E_UNALIGNED_RANGE).into());
+        }
+        if start > self.size || len > self.size - start {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, E_RANGE_OUT_OF_BOUNDS).into());
+        }
+        let mut magic = self.magic;
+        for _ in 0..start / 4 {
+            advance_magic(&mut magic);
+        }
+        self.seek_body(r, start)?;
+        run_codec(buf, r, w, len, magic, &mut |_| {})?;
+        Ok(())
+    }
+
+    /// Decrypts the rest of the body from `start` on into `w`, e.g. to resume
+    /// an extraction that stopped at `start` bytes. As with
+    /// [`extract_range`](Self::extract_range), `start` must be a multiple of 4.
+    pub fn read_from(
+        &self,
+        buf: &mut [u8],
+        r: &mut (impl Read + Seek),
+        w: &mut impl Write,
+        start: u32,
+    ) -> Result<(), RgssError> {
+        self.extract_range(buf, r, w, start, self.size.saturating_sub(start))
+    }
+
+    /// Encrypts the contents of `r` into the body, with the same requirements
+    /// on `buf` as [`read`](Self::read). `w` is seeked to the entry offset;
+    /// [`RGSSArchive::write_to`] writes whole archives without seeking.
     pub fn write(
         &self,
         buf: &mut [u8],
         w: &mut (impl Write + Seek),
         r: &mut impl Read,
+    ) -> io::Result<()> {
+        self.write_with_progress(buf, w, r, |_, _, _| {})
+    }
+
+    /// Like [`write`](Self::write), reporting progress the same way as
+    /// [`read_with_progress`](Self::read_with_progress).
+    pub fn write_with_progress(
+        &self,
+        buf: &mut [u8],
+        w: &mut (impl Write + Seek),
+        r: &mut impl Read,
+        mut progress: impl FnMut(&str, u64, u64),
     ) -> io::Result<()> {
         w.seek(SeekFrom::Start(self.offset as u64))?;
-        run_codec(buf, r, w, self.size, self.magic)?;
+        self.encrypt(buf, r, w, &mut |done| {
+            progress(&self.name, done, self.size as u64)
+        })
+    }
+
+    /// Encrypts the contents of `r` into `w` at its current position, for
+    /// writers that cannot seek. As with [`write`](Self::write), `r` must
+    /// hold exactly `size` bytes.
+    pub fn write_sequential(
+        &self,
+        buf: &mut [u8],
+        w: &mut impl Write,
+        r: &mut impl Read,
+    ) -> io::Result<()> {
+        self.encrypt(buf, r, w, &mut |_| {})
+    }
+
+    /// Copies the encrypted body from `r` into `w` as it is. The copy only
+    /// decrypts correctly where the entry has the same key, e.g. when
+    /// rewriting an archive whose table is unchanged up to this entry.
+    pub fn copy_raw(
+        &self,
+        r: &mut (impl Read + Seek),
+        w: &mut impl Write,
+    ) -> Result<(), RgssError> {
+        self.seek_body(r, 0)?;
+        if io::copy(&mut r.take(self.size as u64), w)? < self.size as u64 {
+            return Err(RgssError::TruncatedEntry);
+        }
+        Ok(())
+    }
+
+    /// Encrypts exactly `size` bytes of `r` into `w`. A source that is shorter
+    /// or longer, e.g. a file that changed since its size was taken, would
+    /// misalign everything after the body, so it is an error.
+    fn encrypt(
+        &self,
+        buf: &mut [u8],
+        r: &mut impl Read,
+        w: &mut impl Write,
+        progress: &mut impl FnMut(u64),
+    ) -> io::Result<()> {
+        let written = run_codec(buf, r, w, self.size, self.magic, progress)?;
+        if written < self.size as u64 || r.read(&mut [0])? != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", E_SIZE_MISMATCH, self.name),
+            ));
+        }
         Ok(())
     }
 }
 
+/// Character encoding of entry names as stored in the archive. Archives made
+/// with Japanese releases of RPG Maker commonly use Shift-JIS.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NameEncoding {
+    #[default]
+    Utf8,
+    ShiftJis,
+}
+
+#[cfg(feature = "std")]
+impl NameEncoding {
+    fn decode(self, name: Vec<u8>) -> Result<String, RgssError> {
+        let name = match self {
+            NameEncoding::Utf8 => String::from_utf8(name).ok(),
+            NameEncoding::ShiftJis => SHIFT_JIS
+                .decode_without_bom_handling_and_without_replacement(&name)
+                .map(Cow::into_owned),
+        };
+        name.map(|name| name.replace('\\', "/"))
+            .ok_or(RgssError::InvalidName {
+                encoding: self,
+                offset: None,
+            })
+    }
+
+    fn encode(self, name: &str) -> Result<Vec<u8>, RgssError> {
+        let name = name.replace('/', "\\");
+        match self {
+            NameEncoding::Utf8 => Ok(name.into_bytes()),
+            NameEncoding::ShiftJis => match SHIFT_JIS.encode(&name) {
+                (name, _, false) => Ok(name.into_owned()),
+                (_, _, true) => Err(RgssError::InvalidName {
+                    encoding: self,
+                    offset: None,
+                }),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for NameEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NameEncoding::Utf8 => "UTF-8",
+            NameEncoding::ShiftJis => "Shift_JIS",
+        })
+    }
+}
+
+/// Order to put entries in with [`RGSSArchive::sort_entries`]. Sorting is
+/// stable, so entries that compare equal keep their relative order.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EntryOrder {
+    /// Leaves the entries in the order they were added or read.
+    #[default]
+    AsInserted,
+    /// Uses [`RGSSArchiveEntry::cmp_by_name`].
+    ByName,
+    /// Compares names by their uppercased form.
+    ByNameCaseInsensitive,
+    BySize,
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReadOptions {
+    /// Maximum accepted length of an entry name in bytes. Names in real
+    /// archives are short relative paths, so anything longer is treated as
+    /// corrupt instead of being allocated.
+    pub max_name_len: u32,
+    /// Encoding used to decode entry names. It is remembered by the archive
+    /// and used again when the archive is written.
+    pub encoding: NameEncoding,
+    /// Maximum number of entries accepted in a table, so that a corrupt table
+    /// that never terminates cannot exhaust memory.
+    pub max_entries: usize,
+    /// Ends a v1/v2 table at the first record that cannot be valid instead of
+    /// failing, for archives that third-party tools padded after the last
+    /// body. A record is taken to be padding if it cannot be decoded, if its
+    /// name holds control characters or if its body runs past the end of the
+    /// file. Off by default, since it also hides real corruption.
+    pub allow_trailing_data: bool,
+}
+
+#[cfg(feature = "std")]
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            max_name_len: 0x10000,
+            encoding: NameEncoding::default(),
+            max_entries: 1_000_000,
+            allow_trailing_data: false,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ReadOptions {
+    fn check_name_len(&self, len: u32) -> Result<usize, RgssError> {
+        if len > self.max_name_len {
+            return Err(RgssError::NameTooLong(len));
+        }
+        Ok(len as usize)
+    }
+
+    fn check_entry_count(&self, count: usize) -> Result<(), RgssError> {
+        if count >= self.max_entries {
+            return Err(RgssError::TooManyEntries(self.max_entries));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RGSSArchive {
+    /// The version byte of the header: 1 for RPG Maker XP (`.rgssad`), 2 for
+    /// VX (`.rgss2a`) and 3 for VX Ace (`.rgss3a`). Versions 1 and 2 share one
+    /// format; the byte is kept as read and written back unchanged, so a v2
+    /// archive stays v2.
     pub version: u8,
     pub entries: Vec<RGSSArchiveEntry>,
     pub magic: u32,
+    pub encoding: NameEncoding,
 }
 
+/// Parses an archive held entirely in memory, as
+/// [`RGSSArchive::from_bytes`] does:
+///
+/// ```
+/// use rgssad::RGSSArchive;
+///
+/// // A v1 archive with one entry, `a`, holding `hi`.
+/// let data = b"RGSSAD\x00\x01\xff\xca\xad\xde\x94\xb4\xda\x43\x9f\x95\x93";
+/// let archive = RGSSArchive::try_from(&data[..])?;
+/// assert_eq!(archive.version, 1);
+/// assert_eq!(archive.entries[0].name, "a");
+/// assert_eq!(archive.entries[0].extract_to_vec(data)?, b"hi");
+/// # Ok::<(), rgssad::RgssError>(())
+/// ```
+#[cfg(feature = "std")]
+impl TryFrom<&[u8]> for RGSSArchive {
+    type Error = RgssError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        RGSSArchive::from_bytes(data)
+    }
+}
+
+/// Iterates over the entries in table order, as `archive.entries.iter()`
+/// does:
+///
+/// ```
+/// use rgssad::{RGSSArchive, RGSSArchiveEntry};
+///
+/// let mut archive = RGSSArchive::default();
+/// for name in ["Data/Scripts.rxdata", "Graphics/Titles/title.png"] {
+///     archive.push_entry(RGSSArchiveEntry {
+///         name: name.to_owned(),
+///         size: 4,
+///         ..RGSSArchiveEntry::default()
+///     })?;
+/// }
+/// for entry in &mut archive {
+///     entry.size *= 2;
+/// }
+/// let mut total = 0;
+/// for entry in &archive {
+///     total += entry.size;
+/// }
+/// assert_eq!(total, 16);
+/// # Ok::<(), rgssad::RgssError>(())
+/// ```
+#[cfg(feature = "std")]
+impl<'a> IntoIterator for &'a RGSSArchive {
+    type Item = &'a RGSSArchiveEntry;
+    type IntoIter = std::slice::Iter<'a, RGSSArchiveEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> IntoIterator for &'a mut RGSSArchive {
+    type Item = &'a mut RGSSArchiveEntry;
+    type IntoIter = std::slice::IterMut<'a, RGSSArchiveEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter_mut()
+    }
+}
+
+#[cfg(feature = "std")]
 impl RGSSArchive {
-    pub fn read_header(&mut self, r: &mut impl Read) -> io::Result<()> {
+    /// Sets the base key of a v3 archive. It seeds the table encryption and
+    /// the keys of new entries, i.e. those whose offset and key are both zero.
+    /// Zero is accepted but yields a weak, predictable key, so a random value
+    /// is preferable.
+    pub fn with_magic(mut self, magic: u32) -> Self {
+        self.magic = magic;
+        self
+    }
+
+    /// Parses the header and entry table. Malformed or hostile input is
+    /// reported as an error and never causes a panic; name lengths and entry
+    /// counts are bounded by [`ReadOptions`] before anything is allocated.
+    pub fn open(r: &mut (impl Read + Seek)) -> Result<RGSSArchive, RgssError> {
+        RGSSArchive::open_with(r, &ReadOptions::default())
+    }
+
+    pub fn open_with(
+        r: &mut (impl Read + Seek),
+        options: &ReadOptions,
+    ) -> Result<RGSSArchive, RgssError> {
+        let mut archive = RGSSArchive::default();
+        archive.read_header(r)?;
+        archive.read_entries_with(r, options)?;
+        Ok(archive)
+    }
+
+    /// Parses an archive from a reader that cannot seek, such as a pipe. A v3
+    /// table is read without touching the bodies after it; v1/v2 records are
+    /// interleaved with the bodies, which are read and discarded to reach them.
+    pub fn open_sequential(r: &mut impl Read) -> Result<RGSSArchive, RgssError> {
+        RGSSArchive::open_sequential_with(r, &ReadOptions::default())
+    }
+
+    pub fn open_sequential_with(
+        r: &mut impl Read,
+        options: &ReadOptions,
+    ) -> Result<RGSSArchive, RgssError> {
+        let mut archive = RGSSArchive::default();
+        archive.read_header(r)?;
+        archive.encoding = options.encoding;
+        match archive.version {
+            1 | 2 => {
+                let mut stream = EntryStream::new(r, archive.version, options.clone());
+                while let Some(streamed) = stream.next_entry()? {
+                    archive.entries.push(streamed.entry);
+                }
+            }
+            _ => archive.read_entries_rgss3a(r, options)?,
+        }
+        Ok(archive)
+    }
+
+    /// Parses an archive held entirely in memory. Bodies can then be taken
+    /// from the same bytes with [`RGSSArchiveEntry::extract_to_vec`]:
+    ///
+    /// ```
+    /// use rgssad::{RGSSArchive, RGSSArchiveEntry};
+    ///
+    /// let mut archive = RGSSArchive {
+    ///     version: 1,
+    ///     ..RGSSArchive::default()
+    /// };
+    /// archive.push_entry(RGSSArchiveEntry {
+    ///     name: "Data/Scripts.rxdata".to_owned(),
+    ///     ..RGSSArchiveEntry::default()
+    /// })?;
+    /// let data = archive.write_to_vec(|_| b"scripts".to_vec())?;
+    ///
+    /// let archive = RGSSArchive::from_bytes(&data)?;
+    /// let entry = archive.find("Data/Scripts.rxdata").unwrap();
+    /// assert_eq!(entry.extract_to_vec(&data)?, b"scripts");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_bytes(data: &[u8]) -> Result<RGSSArchive, RgssError> {
+        RGSSArchive::open(&mut Cursor::new(data))
+    }
+
+    /// Parses a v1/v2 archive from a reader that cannot seek, such as a pipe.
+    /// Entries are produced one at a time by [`EntryStream::next_entry`]
+    /// together with their decrypted bodies.
+    pub fn stream_entries<R: Read>(r: R) -> Result<EntryStream<R>, RgssError> {
+        RGSSArchive::stream_entries_with(r, ReadOptions::default())
+    }
+
+    pub fn stream_entries_with<R: Read>(
+        mut r: R,
+        options: ReadOptions,
+    ) -> Result<EntryStream<R>, RgssError> {
+        let mut archive = RGSSArchive::default();
+        archive.read_header(&mut r)?;
+        match archive.version {
+            1 | 2 => Ok(EntryStream::new(r, archive.version, options)),
+            version => Err(RgssError::UnsupportedVersion(version)),
+        }
+    }
+
+    /// Copies the table with every entry's offset and key cleared, so that
+    /// writing the copy lays the entries out afresh instead of reusing
+    /// positions in the file `self` was read from. The version, encoding and
+    /// archive key are kept.
+    pub fn metadata_only(&self) -> RGSSArchive {
+        RGSSArchive {
+            version: self.version,
+            entries: self
+                .entries
+                .iter()
+                .map(|entry| RGSSArchiveEntry {
+                    offset: 0,
+                    magic: 0,
+                    ..entry.clone()
+                })
+                .collect(),
+            magic: self.magic,
+            encoding: self.encoding,
+        }
+    }
+
+    pub fn entries(&self) -> &[RGSSArchiveEntry] {
+        &self.entries
+    }
+
+    /// Adds an entry to the end of the table, converting `\` in its name to
+    /// `/` as parsing does. Fails if an entry of the same name is already
+    /// present, which pushing to `entries` directly would not catch until the
+    /// archive is written.
+    pub fn push_entry(&mut self, mut entry: RGSSArchiveEntry) -> Result<(), RgssError> {
+        entry.name = entry.name.replace('\\', "/");
+        if self.find(&entry.name).is_some() {
+            return Err(RgssError::DuplicateNames(vec![entry.name]));
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Looks up an entry by name. Either slash direction is accepted, matching
+    /// the normalization applied when the table is parsed.
+    pub fn find(&self, name: &str) -> Option<&RGSSArchiveEntry> {
+        let name = name.replace('\\', "/");
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Maps every entry name to its position in `entries`, for callers doing
+    /// many lookups. Names are stored with `/`, so keys given with `\` have
+    /// to be converted first. As with [`find`](Self::find), the first of
+    /// several entries with the same name wins. The index borrows the
+    /// archive and has to be rebuilt after `entries` changes.
+    pub fn build_index(&self) -> HashMap<&str, usize> {
+        let mut index = HashMap::with_capacity(self.entries.len());
+        for (i, entry) in self.entries.iter().enumerate() {
+            index.entry(&*entry.name).or_insert(i);
+        }
+        index
+    }
+
+    /// Returns the first entry that comes before an entry it follows in
+    /// `template`, comparing only names present in both. Entries are written
+    /// in `entries` order, which for v1/v2 fixes the byte layout, so this
+    /// catches a reordering before it changes every offset after it.
+    pub fn find_reordered(&self, template: &RGSSArchive) -> Option<&str> {
+        let index = template.build_index();
+        let mut last = 0;
+        for entry in &self.entries {
+            if let Some(&i) = index.get(&*entry.name) {
+                if i < last {
+                    return Some(&entry.name);
+                }
+                last = i;
+            }
+        }
+        None
+    }
+
+    pub fn sort_entries(&mut self, order: EntryOrder) {
+        match order {
+            EntryOrder::AsInserted => {}
+            EntryOrder::ByName => self.entries.sort_by(RGSSArchiveEntry::cmp_by_name),
+            EntryOrder::ByNameCaseInsensitive => self
+                .entries
+                .sort_by_cached_key(|entry| entry.name.to_uppercase()),
+            EntryOrder::BySize => self.entries.sort_by_key(|entry| entry.size),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the combined size of all entry bodies, i.e. the number of bytes
+    /// a full extraction writes.
+    pub fn total_size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size as u64).sum()
+    }
+
+    /// Returns the number of bytes the header and entry table take up once
+    /// written. For v3 this is where the first body starts; v1/v2 records are
+    /// interleaved with the bodies, so there it is the total of the header and
+    /// every record.
+    pub fn table_size(&self) -> Result<u64, RgssError> {
+        let (header, record) = match self.version {
+            3 => (16, 16),
+            _ => (8, 8),
+        };
+        self.entries.iter().try_fold(header, |size, entry| {
+            Ok(size + record + self.encoding.encode(&entry.name)?.len() as u64)
+        })
+    }
+
+    /// Returns the name of every entry with the byte range its encrypted body
+    /// occupies in the file, for callers that slice the file themselves and
+    /// decrypt with [`Keystream`] and the entry key.
+    pub fn iter_with_offsets(&self) -> impl Iterator<Item = (&str, Range<u64>)> + '_ {
+        self.entries.iter().map(|entry| {
+            let start = entry.offset as u64;
+            (&*entry.name, start..start + entry.size as u64)
+        })
+    }
+
+    /// Returns the offset just past the last byte covered by the archive: the
+    /// end of the furthest body, or the end of the empty table if there are no
+    /// entries. For a well-formed archive this equals the file length.
+    pub fn data_end(&self) -> u64 {
+        self.entries
+            .iter()
+            .map(|entry| entry.offset as u64 + entry.size as u64)
+            .max()
+            .unwrap_or(if self.version == 3 { 16 } else { 8 })
+    }
+
+    /// Renames an entry, returning `false` if there is no entry called `from`
+    /// or another entry is already called `to`. Offsets and keys are left for
+    /// the writer to recompute.
+    pub fn rename_entry(&mut self, from: &str, to: &str) -> bool {
+        let from = from.replace('\\', "/");
+        let to = to.replace('\\', "/");
+        if from != to && self.find(&to).is_some() {
+            return false;
+        }
+        match self.entries.iter_mut().find(|entry| entry.name == from) {
+            Some(entry) => {
+                entry.name = to;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes an entry, returning `false` if there is no entry with that name.
+    pub fn remove_entry(&mut self, name: &str) -> bool {
+        let name = name.replace('\\', "/");
+        match self.entries.iter().position(|entry| entry.name == name) {
+            Some(i) => {
+                self.entries.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Decrypts the named entry into `w`, returning `false` if no entry has
+    /// that name.
+    pub fn extract(
+        &self,
+        name: &str,
+        buf: &mut [u8],
+        r: &mut (impl Read + Seek),
+        w: &mut impl Write,
+    ) -> Result<bool, RgssError> {
+        match self.find(name) {
+            Some(entry) => {
+                entry.read(buf, r, w)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Extracts every entry for which `name_fn` returns a path, writing it to
+    /// that path under `dir` and creating any missing directories. Entries
+    /// mapped to `None` are skipped. A typical `name_fn` starts from
+    /// [`sanitize_name`]; whatever it returns must still be relative and free
+    /// of `..`, or the entry fails with [`RgssError::UnsafeName`].
+    pub fn unpack_with(
+        &self,
+        r: &mut (impl Read + Seek),
+        dir: &Path,
+        name_fn: impl Fn(&str) -> Option<PathBuf>,
+    ) -> Result<(), RgssError> {
+        let mut buf = codec_buffer(8192);
+        for entry in &self.entries {
+            let path = match name_fn(&entry.name) {
+                Some(path) => path,
+                None => continue,
+            };
+            let relative = path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+            if !relative || path.file_name().is_none() {
+                return Err(RgssError::UnsafeName(entry.name.clone()));
+            }
+            let mut out = create_file(dir.join(path))?;
+            entry.read(&mut buf, r, &mut out)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the version conventionally used for files with extension `ext`
+    /// (without the dot): `rgssad` for RPG Maker XP, `rgss2a` for VX and
+    /// `rgss3a` for VX Ace. Versions 1 and 2 share one format and differ only
+    /// by this convention.
+    pub fn version_for_extension(ext: &str) -> Option<u8> {
+        match ext.to_ascii_lowercase().as_str() {
+            "rgssad" => Some(1),
+            "rgss2a" => Some(2),
+            "rgss3a" => Some(3),
+            _ => None,
+        }
+    }
+
+    /// The inverse of [`version_for_extension`](Self::version_for_extension).
+    pub fn default_extension(version: u8) -> Option<&'static str> {
+        match version {
+            1 => Some("rgssad"),
+            2 => Some("rgss2a"),
+            3 => Some("rgss3a"),
+            _ => None,
+        }
+    }
+
+    /// Reads only the 8-byte header and returns the format version, leaving `r`
+    /// positioned at the start of the entry table.
+    pub fn detect_version(r: &mut impl Read) -> Result<u8, RgssError> {
         let mut header = [0; 8];
         r.read_exact(&mut header)?;
-        if &header[..6] != b"RGSSAD" {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, E_INVALID_HEADER));
+        // "RGSSAD", a reserved zero byte and the version.
+        if &header[..6] != b"RGSSAD" || header[6] != 0 {
+            return Err(RgssError::InvalidHeader(header));
         }
-        self.version = header[7];
-        if !(1..=3).contains(&self.version) {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                E_UNSUPPORTED_VERSION,
-            ));
+        let version = header[7];
+        if !(1..=3).contains(&version) {
+            return Err(RgssError::UnsupportedVersion(version));
         }
+        Ok(version)
+    }
+
+    pub fn read_header(&mut self, r: &mut impl Read) -> Result<(), RgssError> {
+        self.version = RGSSArchive::detect_version(r)?;
         Ok(())
     }
 
-    pub fn read_entries(&mut self, r: &mut (impl Read + Seek)) -> io::Result<()> {
+    pub fn read_entries(&mut self, r: &mut (impl Read + Seek)) -> Result<(), RgssError> {
+        self.read_entries_with(r, &ReadOptions::default())
+    }
+
+    pub fn read_entries_with(
+        &mut self,
+        r: &mut (impl Read + Seek),
+        options: &ReadOptions,
+    ) -> Result<(), RgssError> {
+        self.encoding = options.encoding;
         match self.version {
-            1 | 2 => self.read_entries_rgssad(r),
-            3 => self.read_entries_rgss3a(r),
-            _ => Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                E_UNSUPPORTED_VERSION,
-            )),
+            1 | 2 => self.read_entries_rgssad(r, options),
+            3 => self.read_entries_rgss3a(r, options),
+            version => Err(RgssError::UnsupportedVersion(version)),
         }
     }
 
-    fn read_entries_rgssad(&mut self, r: &mut (impl Read + Seek)) -> io::Result<()> {
-        let mut magic = 0xdeadcafe;
+    fn read_entries_rgssad(
+        &mut self,
+        r: &mut (impl Read + Seek),
+        options: &ReadOptions,
+    ) -> Result<(), RgssError> {
+        let start = r.stream_position()?;
+        let len = r.seek(SeekFrom::End(0))?;
+        r.seek(SeekFrom::Start(start))?;
+        let mut magic = RGSSAD_INITIAL_MAGIC;
+        let mut position = 8;
         loop {
-            let mut name = vec![
-                0;
-                match r.read_u32_le() {
-                    Ok(x) => x ^ advance_magic(&mut magic),
-                    Err(_) => break,
-                } as usize
-            ];
-            r.read_exact(&mut name)?;
-            for b in name.iter_mut() {
-                *b ^= advance_magic(&mut magic) as u8;
-            }
-            for b in name.iter_mut() {
-                if *b == b'\\' {
-                    *b = b'/';
+            let (name, size) = match read_record_rgssad(r, &mut magic, options, position) {
+                Ok(Some((name, size, _))) => (name, size),
+                Ok(None) => break,
+                Err(RgssError::Io(e)) if e.kind() != io::ErrorKind::UnexpectedEof => {
+                    return Err(e.into())
                 }
-            }
-            let name = match String::from_utf8(name) {
-                Ok(x) => x,
-                Err(_) => break,
-            };
-            let size = match r.read_u32_le() {
-                Ok(x) => x ^ advance_magic(&mut magic),
-                Err(_) => break,
+                Err(_) if options.allow_trailing_data => break,
+                Err(e) => return Err(e),
             };
-            let offset = r.stream_position()? as u32;
+            // Seeking past the end succeeds, so a body cut off by the end of
+            // the file would otherwise end the table as if it were complete.
+            let end = r.stream_position()? + size as u64;
+            if options.allow_trailing_data && (end > len || name.chars().any(char::is_control)) {
+                break;
+            }
+            if end > len {
+                return Err(RgssError::TruncatedEntry);
+            }
+            options.check_entry_count(self.entries.len())?;
+            let offset = r
+                .stream_position()?
+                .try_into()
+                .map_err(|_| RgssError::OffsetOverflow(name.clone()))?;
             r.seek(SeekFrom::Current(size as i64))?;
             self.entries.push(RGSSArchiveEntry {
                 name,
@@ -144,146 +1066,447 @@ impl RGSSArchive {
                 offset,
                 magic,
             });
+            position = offset as u64 + size as u64;
         }
         Ok(())
     }
 
-    fn read_entries_rgss3a(&mut self, r: &mut impl Read) -> io::Result<()> {
+    fn read_entries_rgss3a(
+        &mut self,
+        r: &mut impl Read,
+        options: &ReadOptions,
+    ) -> Result<(), RgssError> {
         let magic = r.read_u32_le()?;
         self.magic = magic;
-        let xor = magic.wrapping_mul(9).wrapping_add(3);
-        loop {
-            let offset: u32 = match r.read_u32_le() {
-                Ok(x) => x ^ xor,
-                Err(_) => break,
-            };
+        let xor = rgss3a_xor(magic);
+        let mut position = 12;
+        // The table is terminated by an offset that decrypts to zero. A table
+        // that instead runs up to the end of the file is accepted as well.
+        while let Some(offset) = r.read_u32_le_or_eof()? {
+            let offset = offset ^ xor;
             if offset == 0 {
                 break;
             }
-            let size: u32 = match r.read_u32_le() {
-                Ok(x) => x ^ xor,
-                Err(_) => break,
-            };
-            let magic: u32 = match r.read_u32_le() {
-                Ok(x) => x ^ xor,
-                Err(_) => break,
-            };
-            let mut name = vec![
-                0;
-                match r.read_u32_le() {
-                    Ok(x) => x ^ xor,
-                    Err(_) => break,
-                } as usize
-            ];
-            r.read_exact(&mut name)?;
+            options.check_entry_count(self.entries.len())?;
+            let size = r.read_u32_le().map_err(RgssError::truncated)? ^ xor;
+            let magic = r.read_u32_le().map_err(RgssError::truncated)? ^ xor;
+            let name_len = r.read_u32_le().map_err(RgssError::truncated)? ^ xor;
+            let mut name = vec![0; options.check_name_len(name_len)?];
+            r.read_exact(&mut name).map_err(RgssError::truncated)?;
             for (i, b) in name.iter_mut().enumerate() {
                 *b ^= xor.to_le_bytes()[i % 4];
             }
-            for b in name.iter_mut() {
-                if *b == b'\\' {
-                    *b = b'/';
-                }
-            }
-            let name = match String::from_utf8(name) {
-                Ok(x) => x,
-                Err(_) => break,
-            };
+            let name = self.encoding.decode(name).map_err(|e| e.at(position))?;
             self.entries.push(RGSSArchiveEntry {
                 name,
                 size,
                 offset,
                 magic,
             });
+            position += 16 + name_len as u64;
         }
         Ok(())
     }
 
     pub fn write_header(&self, w: &mut impl Write) -> io::Result<()> {
         if !(1..=3).contains(&self.version) {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                E_UNSUPPORTED_VERSION,
-            ));
+            return Err(RgssError::UnsupportedVersion(self.version).into());
         }
         w.write_all(&[b'R', b'G', b'S', b'S', b'A', b'D', b'\0', self.version])?;
         Ok(())
     }
 
+    /// Checks that no two entries end up with the same stored name once `/` is
+    /// turned into `\\`, which readers would be unable to tell apart. Every
+    /// name involved in a collision is listed in the error.
+    pub fn check_names(&self) -> Result<(), RgssError> {
+        let mut seen = HashMap::new();
+        let mut duplicates = Vec::new();
+        for entry in &self.entries {
+            let key = entry.name.replace('/', "\\");
+            match seen.get(&key) {
+                Some(&first) => {
+                    if !duplicates.contains(&first) {
+                        duplicates.push(first);
+                    }
+                    duplicates.push(&entry.name);
+                }
+                None => {
+                    seen.insert(key, &entry.name);
+                }
+            }
+        }
+        if duplicates.is_empty() {
+            return Ok(());
+        }
+        Err(RgssError::DuplicateNames(
+            duplicates.into_iter().cloned().collect(),
+        ))
+    }
+
+    /// Writes the entry table for `self.version`, assigning every entry the
+    /// offset and key its body must be written with. The version may differ
+    /// from the one the entries were read with: v1/v2 keys are always derived
+    /// from the table, and v3 stores whichever key each entry has. Since the
+    /// offsets, and for v1/v2 the keys, are recomputed, bodies have to be
+    /// re-encrypted from their plain contents, e.g. with
+    /// [`write_to`](Self::write_to) or [`RGSSArchiveEntry::write`]. Encrypted
+    /// bodies copied over from the original file are only valid if nothing
+    /// about the table changed.
+    ///
+    /// Bodies are laid out back to back in table order, so rewriting an
+    /// archive read from a file keeps every offset up to the first entry whose
+    /// name or size changed. For v1/v2 this holds for any valid file, since
+    /// the records leave no room for gaps between bodies. The order of
+    /// `entries` is therefore significant; see
+    /// [`find_reordered`](Self::find_reordered).
+    ///
+    /// An archive without entries is valid: v1/v2 consist of the header
+    /// alone, and v3 of the header, the archive key and the table terminator.
+    /// Both read back as an archive with no entries.
     pub fn write_entries(&mut self, w: &mut impl Write) -> io::Result<()> {
+        self.check_names()?;
         match self.version {
-            1 | 2 => self.write_entries_rgssad(w),
-            3 => self.write_entries_rgss3a(w),
-            _ => Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                E_UNSUPPORTED_VERSION,
-            )),
+            1 | 2 => {
+                self.layout_rgssad()?;
+                self.write_entries_rgssad(w)
+            }
+            3 => {
+                self.layout_rgss3a(false)?;
+                self.write_entries_rgss3a(w)
+            }
+            version => Err(RgssError::UnsupportedVersion(version).into()),
         }
     }
 
-    fn write_entries_rgssad(&mut self, w: &mut impl Write) -> io::Result<()> {
-        let mut offset = 8u32;
-        let mut magic = 0xdeadcafe;
-        for entry in &mut self.entries {
-            let name_len: u32 = entry.name.len().try_into().unwrap();
-            w.write_u32_le(name_len ^ advance_magic(&mut magic))?;
-            let mut name = entry.name.as_bytes().to_owned();
-            for b in name.iter_mut() {
-                if *b == b'/' {
-                    *b = b'\\';
+    /// Returns the size of the file that writing the archive would produce,
+    /// assigning offsets and keys as [`write_entries`](Self::write_entries)
+    /// does. Nothing is written anywhere, so this takes time in proportion to
+    /// the table alone.
+    pub fn computed_size(&mut self) -> io::Result<u64> {
+        self.check_names()?;
+        match self.version {
+            1 | 2 => Ok(self.layout_rgssad()?),
+            3 => {
+                self.layout_rgss3a(false)?;
+                Ok(self.data_end())
+            }
+            version => Err(RgssError::UnsupportedVersion(version).into()),
+        }
+    }
+
+    /// Writes the complete archive in a single forward pass: the header, the
+    /// table and every body, obtaining each body from `open`. Offsets and keys
+    /// are assigned as by [`write_entries`](Self::write_entries), and `w` does
+    /// not need to be seekable. For v3 every offset is known once the names
+    /// are, so the table is written first and the bodies follow it in order.
+    pub fn write_to<R: Read, W: Write>(
+        &mut self,
+        buf: &mut [u8],
+        w: &mut W,
+        open: impl FnMut(&RGSSArchiveEntry) -> io::Result<R>,
+    ) -> io::Result<()> {
+        self.write_to_with_progress(buf, w, open, |_, _, _| {})
+    }
+
+    /// Builds the complete archive in memory, taking the body of every entry
+    /// from `bodies`. Entry sizes are set from the bodies returned.
+    pub fn write_to_vec(&mut self, mut bodies: impl FnMut(&str) -> Vec<u8>) -> io::Result<Vec<u8>> {
+        let bodies: Vec<_> = self
+            .entries
+            .iter()
+            .map(|entry| bodies(&entry.name))
+            .collect();
+        for (entry, body) in self.entries.iter_mut().zip(&bodies) {
+            entry.size = body
+                .len()
+                .try_into()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, E_ENTRY_TOO_LARGE))?;
+        }
+        let mut out = Vec::new();
+        let mut bodies = bodies.iter();
+        self.write_to(&mut [0; 8192], &mut out, |_| {
+            Ok(&bodies.next().unwrap()[..])
+        })?;
+        Ok(out)
+    }
+
+    /// Like [`write_to`](Self::write_to), reporting progress for every body as
+    /// [`RGSSArchiveEntry::write_with_progress`] does.
+    pub fn write_to_with_progress<R: Read, W: Write>(
+        &mut self,
+        buf: &mut [u8],
+        w: &mut W,
+        mut open: impl FnMut(&RGSSArchiveEntry) -> io::Result<R>,
+        mut progress: impl FnMut(&str, u64, u64),
+    ) -> io::Result<()> {
+        self.write_to_with(w, |entry, w| {
+            entry.encrypt(buf, &mut open(entry)?, w, &mut |done| {
+                progress(&entry.name, done, entry.size as u64)
+            })
+        })
+    }
+
+    /// Like [`write_to`](Self::write_to), leaving every body to `write_body`.
+    /// It is called in table order with the entry's offset and key assigned,
+    /// and must write exactly `size` encrypted bytes, e.g. with
+    /// [`RGSSArchiveEntry::write_sequential`] or
+    /// [`RGSSArchiveEntry::copy_raw`].
+    pub fn write_to_with<W: Write>(
+        &mut self,
+        w: &mut W,
+        mut write_body: impl FnMut(&RGSSArchiveEntry, &mut W) -> io::Result<()>,
+    ) -> io::Result<()> {
+        self.check_names()?;
+        match self.version {
+            1 | 2 => {
+                self.layout_rgssad()?;
+                self.write_header(w)?;
+                self.write_records_rgssad(w, write_body)
+            }
+            3 => {
+                self.layout_rgss3a(false)?;
+                self.write_header(w)?;
+                self.write_entries_rgss3a(w)?;
+                for entry in &self.entries {
+                    write_body(entry, w)?;
                 }
+                Ok(())
             }
-            for b in name.iter_mut() {
-                *b ^= advance_magic(&mut magic) as u8;
+            version => Err(RgssError::UnsupportedVersion(version).into()),
+        }
+    }
+
+    /// Like [`write_to_with`](Self::write_to_with), but keeps the offset of
+    /// every v3 entry that has one, so that rewriting an archive only moves
+    /// the bodies that have to move. An offset is kept if the body lies past
+    /// the new table and clear of the bodies kept before it. The other
+    /// entries, including new ones with an offset of zero, are placed after
+    /// the furthest kept body. Bodies are written in order of offset, with
+    /// any gaps between them filled with zeros.
+    ///
+    /// v1/v2 bodies follow their records, so offsets are already kept up to
+    /// the first entry whose name or size changed, and this is the same as
+    /// `write_to_with`.
+    pub fn write_to_keeping_offsets<W: Write>(
+        &mut self,
+        w: &mut W,
+        mut write_body: impl FnMut(&RGSSArchiveEntry, &mut W) -> io::Result<()>,
+    ) -> io::Result<()> {
+        if self.version != 3 {
+            return self.write_to_with(w, write_body);
+        }
+        self.check_names()?;
+        self.layout_rgss3a(true)?;
+        self.write_header(w)?;
+        self.write_entries_rgss3a(w)?;
+        let mut position = self.table_size()?;
+        for i in self.by_offset() {
+            let entry = &self.entries[i];
+            io::copy(&mut io::repeat(0).take(entry.offset as u64 - position), w)?;
+            write_body(entry, w)?;
+            position = entry.offset as u64 + entry.size as u64;
+        }
+        Ok(())
+    }
+
+    fn write_entries_rgssad(&self, w: &mut impl Write) -> io::Result<()> {
+        self.write_records_rgssad(w, |entry, w| {
+            io::copy(&mut io::repeat(0).take(entry.size as u64), w)?;
+            Ok(())
+        })
+    }
+
+    /// Assigns the offset and key of every v1/v2 entry, each body following
+    /// its record, and returns the size of the file. Fails if a body would
+    /// end past 4 GiB.
+    fn layout_rgssad(&mut self) -> Result<u64, RgssError> {
+        let mut offset = 8u32;
+        let mut magic = RGSSAD_INITIAL_MAGIC;
+        for entry in &mut self.entries {
+            let name_len = self.encoding.encode(&entry.name)?.len();
+            offset = u32::try_from(name_len)
+                .ok()
+                .and_then(|name_len| offset.checked_add(name_len)?.checked_add(8))
+                .ok_or_else(|| RgssError::OffsetOverflow(entry.name.clone()))?;
+            // One key each for the name length, the name bytes and the size.
+            for _ in 0..name_len + 2 {
+                advance_magic(&mut magic);
             }
-            w.write_all(&name)?;
-            w.write_u32_le(entry.size ^ advance_magic(&mut magic))?;
-            offset = offset
-                .checked_add(name_len)
-                .unwrap()
-                .checked_add(8)
-                .unwrap();
             entry.offset = offset;
             entry.magic = magic;
-            w.write_all(&vec![0; entry.size as usize])?;
-            offset = offset.checked_add(entry.size).unwrap();
+            offset = offset
+                .checked_add(entry.size)
+                .ok_or_else(|| RgssError::OffsetOverflow(entry.name.clone()))?;
         }
-        Ok(())
+        Ok(offset as u64)
     }
 
-    fn write_entries_rgss3a(&mut self, w: &mut impl Write) -> io::Result<()> {
-        let mut offset: u32 = 16u32;
+    /// Writes the v1/v2 records as [`layout_rgssad`](Self::layout_rgssad)
+    /// laid them out, calling `write_body` after each one to fill in the body
+    /// that follows it.
+    fn write_records_rgssad<W: Write>(
+        &self,
+        w: &mut W,
+        mut write_body: impl FnMut(&RGSSArchiveEntry, &mut W) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut magic = RGSSAD_INITIAL_MAGIC;
         for entry in &self.entries {
-            let name_len: u32 = entry.name.len().try_into().unwrap();
-            offset = offset
-                .checked_add(name_len)
-                .unwrap()
-                .checked_add(16)
-                .unwrap();
+            let name = self.encoding.encode(&entry.name)?;
+            write_record_rgssad(w, &mut magic, name, entry.size)?;
+            write_body(entry, w)?;
         }
-        for entry in &mut self.entries {
-            entry.offset = offset;
-            offset = offset.checked_add(entry.size).unwrap();
+        Ok(())
+    }
+
+    /// Adds an entry to the end of a v1/v2 archive in place. `self` must be the
+    /// table read from `w`; the record keystream is resumed from its last
+    /// entry, so nothing already written has to change. v3 archives keep their
+    /// table in front of the bodies and cannot be extended this way.
+    ///
+    /// The body is read from the current position of `data` to its end. Its
+    /// length is checked against the 4 GiB limit before anything is written,
+    /// so a body that does not fit leaves the archive as it was.
+    pub fn append(
+        &mut self,
+        w: &mut (impl Write + Seek),
+        name: &str,
+        mut data: impl Read + Seek,
+    ) -> io::Result<()> {
+        if !matches!(self.version, 1 | 2) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                E_APPEND_UNSUPPORTED,
+            ));
+        }
+        let name = name.replace('\\', "/");
+        if self.find(&name).is_some() {
+            return Err(RgssError::DuplicateNames(vec![name]).into());
         }
+        let encoded = self.encoding.encode(&name)?;
+        let mut magic = self
+            .entries
+            .last()
+            .map_or(RGSSAD_INITIAL_MAGIC, |entry| entry.magic);
+        let end = self.data_end();
+        let start = data.stream_position()?;
+        let len = data.seek(SeekFrom::End(0))?.saturating_sub(start);
+        data.seek(SeekFrom::Start(start))?;
+        let size: u32 = len
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, E_ENTRY_TOO_LARGE))?;
+        let offset: u32 = (end + encoded.len() as u64 + 8)
+            .try_into()
+            .ok()
+            .filter(|offset: &u32| offset.checked_add(size).is_some())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, E_ARCHIVE_TOO_LARGE))?;
+        w.seek(SeekFrom::Start(end))?;
+        write_record_rgssad(w, &mut magic, encoded, size)?;
+        let entry = RGSSArchiveEntry {
+            name,
+            size,
+            offset,
+            magic,
+        };
+        entry.write_sequential(&mut [0; 8192], w, &mut data)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Writes the v3 table as [`layout_rgss3a`](Self::layout_rgss3a) laid it
+    /// out.
+    fn write_entries_rgss3a(&self, w: &mut impl Write) -> io::Result<()> {
         let magic = self.magic;
         w.write_u32_le(magic)?;
-        let xor = magic.wrapping_mul(9).wrapping_add(3);
+        let xor = rgss3a_xor(magic);
         for entry in &self.entries {
+            let mut name = self.encoding.encode(&entry.name)?;
             w.write_u32_le(entry.offset ^ xor)?;
             w.write_u32_le(entry.size ^ xor)?;
             w.write_u32_le(entry.magic ^ xor)?;
-            w.write_u32_le(entry.name.len() as u32 ^ xor)?;
-            let mut name = entry.name.as_bytes().to_owned();
-            for b in name.iter_mut() {
-                if *b == b'/' {
-                    *b = b'\\';
-                }
-            }
+            w.write_u32_le(name.len() as u32 ^ xor)?;
             for (i, b) in name.iter_mut().enumerate() {
                 *b ^= xor.to_le_bytes()[i % 4];
             }
             w.write_all(&name)?;
         }
+        // The table ends with an entry whose offset decrypts to zero, i.e. `xor`
+        // itself. Only that one word is written, as RPG Maker VX Ace does, so
+        // the table size above counts 4 bytes for it rather than a record.
         w.write_u32_le(xor)?;
         Ok(())
     }
+
+    /// Assigns the offset and key of every v3 entry, laying the bodies out
+    /// back to back after the table or, with `keep_offsets`, as
+    /// [`write_to_keeping_offsets`] describes. Fails if a body would end past
+    /// 4 GiB.
+    ///
+    /// [`write_to_keeping_offsets`]: Self::write_to_keeping_offsets
+    fn layout_rgss3a(&mut self, keep_offsets: bool) -> Result<(), RgssError> {
+        let mut table_end: u32 = 16u32;
+        for entry in &self.entries {
+            table_end = u32::try_from(self.encoding.encode(&entry.name)?.len())
+                .ok()
+                .and_then(|name_len| table_end.checked_add(name_len)?.checked_add(16))
+                .ok_or_else(|| RgssError::OffsetOverflow(entry.name.clone()))?;
+        }
+        // Entries that have never been laid out, with neither an offset nor
+        // a key, get a key stepped from the archive key, so that bodies are
+        // not all encrypted with the same keystream. A key of zero on an
+        // entry that was read is kept, since its body is encrypted with it.
+        let mut key = self.magic;
+        for entry in &mut self.entries {
+            advance_magic(&mut key);
+            if entry.offset == 0 && entry.magic == 0 {
+                entry.magic = key;
+            }
+        }
+        let kept = match keep_offsets {
+            true => self.kept_offsets_rgss3a(table_end)?,
+            false => vec![false; self.entries.len()],
+        };
+        let mut offset = kept
+            .iter()
+            .zip(&self.entries)
+            .filter(|(kept, _)| **kept)
+            .map(|(_, entry)| entry.offset + entry.size)
+            .max()
+            .unwrap_or(table_end);
+        for (entry, kept) in self.entries.iter_mut().zip(kept) {
+            if !kept {
+                entry.offset = offset;
+                offset = offset
+                    .checked_add(entry.size)
+                    .ok_or_else(|| RgssError::OffsetOverflow(entry.name.clone()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns which entries can keep their offset: going by offset, those
+    /// whose body starts past the table and past every body kept before it.
+    fn kept_offsets_rgss3a(&self, table_end: u32) -> Result<Vec<bool>, RgssError> {
+        let mut kept = vec![false; self.entries.len()];
+        let mut end = table_end;
+        for i in self.by_offset() {
+            let entry = &self.entries[i];
+            if entry.offset >= end {
+                end = entry
+                    .offset
+                    .checked_add(entry.size)
+                    .ok_or_else(|| RgssError::OffsetOverflow(entry.name.clone()))?;
+                kept[i] = true;
+            }
+        }
+        Ok(kept)
+    }
+
+    /// Returns the indices of the entries in order of offset. An empty body
+    /// goes before a body at the same offset, so the bodies follow each other.
+    fn by_offset(&self) -> Vec<usize> {
+        let mut order: Vec<_> = (0..self.entries.len()).collect();
+        order.sort_by_key(|&i| (self.entries[i].offset, self.entries[i].size));
+        order
+    }
 }
@@ -1,11 +1,20 @@
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryInto;
-use std::ffi::OsStr;
-use std::fs::{self, File};
-use std::io;
-use std::path::Path;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
 
+use globset::Glob;
 use regex::Regex;
-use rgssad::{RGSSArchive, RGSSArchiveEntry};
+use rgssad::{
+    codec_buffer, sanitize_name, DecryptReader, NameEncoding, RGSSArchive, RGSSArchiveEntry,
+    ReadOptions,
+};
 use walkdir::WalkDir;
 
 const USAGE: &str = concat!(
@@ -13,25 +22,807 @@ const USAGE: &str = concat!(
     "Commands:\n",
     "    help\n",
     "    version\n",
-    "    list <archive>\n",
-    "    unpack <archive> <dir> [<filter>]\n",
-    "    pack <dir> <archive> [<version>]\n",
-    "    repack <dir> <archive> <template>\n",
+    "    list [--json] [--verbose] <archive>\n",
+    "    unpack [--dry-run] [--overwrite] [--resume] [--flatten] [--skip-empty] [--strip-components <n>] [--glob <pattern>] [--exclude <regex>] [--filter-from <file>] [--min-size <size>] [--max-size <size>] [--manifest <file>] [--timestamps-from <file>] [--compress <gzip|zstd>] <archive> <dir> [<filter>]\n",
+    "    unpack --tar <archive> [<filter>]\n",
+    "    pack [--dry-run] [--no-follow-links] [--skip-empty] [--find-duplicates] [--save-timestamps <file>] <dir> <archive> [<version>]\n",
+    "    pack --manifest <file> [--save-timestamps <file>] <dir> <archive>\n",
+    "    repack [--add-new] [--allow-missing] [--preserve-order] [--keep-offsets] [--checksums <file>] <dir> <archive> <template>\n",
+    "    verify <archive>\n",
+    "    cat <archive> <name> [<output>]\n",
+    "    checksum <archive>\n",
+    "    diff <archive> <archive>\n",
+    "    info <archive>\n",
+    "    decrypt --magic <key> --size <bytes>\n",
+    "Options:\n",
+    "    --encoding <utf-8|shift-jis>\n",
+    "    --buffer-size <bytes>\n",
+    "    --quiet\n",
+    "    --verbose\n",
+    "An <archive> or <output> of - reads from stdin or writes to stdout. An archive\n",
+    "read from stdin is held in memory, since its table is not read in order.\n",
 );
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const E_INVALID_REGEX_FILTER: &str = "Invalid regex filter";
+const E_INVALID_GLOB_FILTER: &str = "Invalid glob filter";
+const E_CONFLICTING_FILTERS: &str = "A regex filter and --glob cannot be used together";
 const E_INVALID_VERSION: &str = "Invalid version";
+const E_INVALID_STRIP_COMPONENTS: &str = "Invalid component count";
+const E_INVALID_ENCODING: &str = "Invalid encoding";
+const E_INVALID_COMPRESSION: &str = "Invalid compression";
+const E_COMPRESSION_UNSUPPORTED: &str =
+    "Compressed output requires the gzip or zstd feature of the same name";
+const E_CONFLICTING_COMPRESSION: &str = "--compress cannot be used with --resume or --tar";
+const E_INVALID_BUFFER_SIZE: &str = "Invalid buffer size";
+const E_INVALID_SIZE: &str = "Invalid size";
+const E_INVALID_MAGIC: &str = "Invalid key";
+const E_SHORT_INPUT: &str = "Input ended early";
+const E_NON_UTF8_NAME: &str =
+    "File name is not valid UTF-8 (rename it; --encoding sets how names are stored)";
+const E_MISSING_VALUE: &str = "Missing value for option";
+const E_VERIFY_FAILED: &str = "Problems found";
+const E_MISSING_FILES: &str = "Files missing from the directory";
+const E_FILE_TOO_LARGE: &str = "File exceeds the 4 GiB entry size limit";
+const E_REORDERED: &str = "Entry moved relative to the template";
+const E_INVALID_CHECKSUMS: &str = "Invalid checksum line";
+const E_OUTPUT_IS_TEMPLATE: &str = "The output must not be the template when reusing bodies";
+const E_ENTRY_NOT_FOUND: &str = "No such entry";
+const E_FILES_EXIST: &str = "Files already exist (use --overwrite to replace them)";
 
-fn ensure_file(path: impl AsRef<Path>) -> io::Result<File> {
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+fn take_option(args: &mut Vec<String>, name: &str) -> io::Result<Option<String>> {
+    match args.iter().position(|arg| arg == name) {
+        Some(i) if i + 1 < args.len() => {
+            args.remove(i);
+            Ok(Some(args.remove(i)))
+        }
+        Some(_) => Err(missing_option(name)),
+        None => Ok(None),
+    }
+}
+
+fn missing_option(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{}: {}", E_MISSING_VALUE, name),
+    )
+}
+
+fn parse_regex(s: &str) -> io::Result<Regex> {
+    Regex::new(s).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: {}", E_INVALID_REGEX_FILTER, e),
+        )
+    })
+}
+
+/// Parses a byte count, optionally followed by `K`, `M` or `G` for powers of
+/// 1024.
+fn parse_size(s: &str) -> io::Result<u64> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: {}", E_INVALID_SIZE, s),
+        )
+    };
+    let (digits, shift) = match s.as_bytes().last().map(u8::to_ascii_uppercase) {
+        Some(b'K') => (&s[..s.len() - 1], 10),
+        Some(b'M') => (&s[..s.len() - 1], 20),
+        Some(b'G') => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    let n: u64 = digits.parse().map_err(|_| invalid())?;
+    n.checked_mul(1 << shift).ok_or_else(invalid)
+}
+
+/// Parses a key given in decimal or, with a `0x` prefix, in hexadecimal.
+fn parse_magic(s: &str) -> io::Result<u32> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: {}", E_INVALID_MAGIC, e),
+        )
+    })
+}
+
+fn parse_encoding(s: &str) -> io::Result<NameEncoding> {
+    match s.to_ascii_lowercase().as_str() {
+        "utf-8" | "utf8" => Ok(NameEncoding::Utf8),
+        "shift-jis" | "shift_jis" | "sjis" => Ok(NameEncoding::ShiftJis),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: {}", E_INVALID_ENCODING, s),
+        )),
+    }
+}
+
+/// Format that `unpack --compress` writes each file in.
+#[derive(Clone, Copy, Debug)]
+enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// Runs `write` with an encoder that compresses into `file`, then finishes
+    /// the stream and hands the file back.
+    fn encode(
+        self,
+        file: File,
+        write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+    ) -> io::Result<File> {
+        match self {
+            Compression::Gzip => encode_gzip(file, write),
+            Compression::Zstd => encode_zstd(file, write),
+        }
+    }
+}
+
+/// Parses a `--compress` format, failing up front if support for it was not
+/// compiled in.
+fn parse_compression(s: &str) -> io::Result<Compression> {
+    let (compression, supported) = match s.to_ascii_lowercase().as_str() {
+        "gzip" | "gz" => (Compression::Gzip, cfg!(feature = "gzip")),
+        "zstd" | "zst" => (Compression::Zstd, cfg!(feature = "zstd")),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: {}", E_INVALID_COMPRESSION, s),
+            ))
+        }
+    };
+    if !supported {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            E_COMPRESSION_UNSUPPORTED,
+        ));
+    }
+    Ok(compression)
+}
+
+#[cfg(feature = "gzip")]
+fn encode_gzip(
+    file: File,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<File> {
+    let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    write(&mut encoder)?;
+    encoder.finish()
+}
+
+#[cfg(not(feature = "gzip"))]
+fn encode_gzip(
+    _file: File,
+    _write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        E_COMPRESSION_UNSUPPORTED,
+    ))
+}
+
+#[cfg(feature = "zstd")]
+fn encode_zstd(
+    file: File,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<File> {
+    let mut encoder = zstd::stream::write::Encoder::new(file, 0)?;
+    write(&mut encoder)?;
+    encoder.finish()
+}
+
+#[cfg(not(feature = "zstd"))]
+fn encode_zstd(
+    _file: File,
+    _write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        E_COMPRESSION_UNSUPPORTED,
+    ))
+}
+
+/// An archive given on the command line, which may be stdin.
+enum Input {
+    File(File),
+    Stdin(Cursor<Vec<u8>>),
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::File(file) => file.read(buf),
+            Input::Stdin(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for Input {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Input::File(file) => file.seek(pos),
+            Input::Stdin(cursor) => cursor.seek(pos),
+        }
+    }
+}
+
+/// Opens the archive at `path`, or reads all of stdin into memory for `-` so
+/// that it can be seeked.
+fn open_archive(path: &str) -> io::Result<Input> {
+    if path == "-" {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        return Ok(Input::Stdin(Cursor::new(data)));
+    }
+    File::open(path).map(Input::File)
+}
+
+/// Opens `path` for writing, or stdout for `-`.
+fn create_output(path: &str) -> io::Result<Box<dyn Write>> {
+    if path == "-" {
+        return Ok(Box::new(io::stdout().lock()));
+    }
+    Ok(Box::new(File::create(path)?))
+}
+
+/// Packs the files in `dir_path` named by the entries of `archive` into
+/// `path`, or stdout for `-`. The layout is checked against the 4 GiB limit
+/// first, so an archive that would not fit leaves no output behind.
+fn write_archive(
+    archive: &mut RGSSArchive,
+    dir_path: &Path,
+    path: &str,
+    buffer_size: usize,
+    verbosity: Verbosity,
+) -> io::Result<()> {
+    archive.computed_size()?;
+    let mut file = BufWriter::new(create_output(path)?);
+    archive.write_to_with_progress(
+        &mut codec_buffer(buffer_size),
+        &mut file,
+        |entry| File::open(dir_path.join(&entry.name)),
+        show_progress("Packing", verbosity),
+    )?;
+    file.flush()
+}
+
+/// Turns a path relative to the packed directory into an entry name. The
+/// components are joined with `/` whatever the host separator is, leaving the
+/// conversion to `\` to the library.
+fn entry_name(path: &Path) -> io::Result<String> {
+    let components = path
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", E_NON_UTF8_NAME, path.display()),
+            )
+        })?;
+    Ok(components.join("/"))
+}
+
+/// Reads the entry names listed one per line in `path`. Blank lines are
+/// skipped and `\` is taken as a separator, as in the archive.
+fn read_name_list(path: String) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.replace('\\', "/"))
+        .collect())
+}
+
+/// Reads the `<name> <crc32>` lines printed by the `checksum` command.
+fn read_checksums(path: String) -> io::Result<HashMap<String, u32>> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.rsplit_once(' ')
+                .and_then(|(name, crc)| Some((name.to_owned(), u32::from_str_radix(crc, 16).ok()?)))
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: {}", E_INVALID_CHECKSUMS, line),
+                    )
+                })
+        })
+        .collect()
+}
+
+fn file_checksum(path: &Path) -> io::Result<u32> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut file = File::open(path)?;
+    let mut buf = [0; 8192];
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(hasher.finalize()),
+            n => hasher.update(&buf[..n]),
+        }
+    }
+}
+
+/// The archive `repack` started from, with the checksums of its files and
+/// whether its v3 offsets are kept.
+struct Template<'a> {
+    archive: &'a RGSSArchive,
+    file: &'a mut Input,
+    checksums: &'a HashMap<String, u32>,
+    keep_offsets: bool,
+}
+
+/// Writes `archive` like [`write_archive`], but copies the encrypted body of
+/// every file that matches its checksum from the template instead of
+/// encrypting it again. That needs the entry to keep its template key: for
+/// v3 the key is stored and kept, while for v1/v2 it follows from the names
+/// of all entries before it. Files are still read to be checksummed. With
+/// `keep_offsets`, bodies stay where the template has them when they fit.
+fn write_archive_reusing(
+    archive: &mut RGSSArchive,
+    dir_path: &Path,
+    path: &str,
+    buffer_size: usize,
+    verbosity: Verbosity,
+    template: Template,
+) -> io::Result<()> {
+    // Kept offsets are read from the entries when writing, so only the
+    // sequential layout can be checked up front without disturbing them.
+    if !template.keep_offsets {
+        archive.computed_size()?;
+    }
+    let mut file = BufWriter::new(create_output(path)?);
+    let mut buf = codec_buffer(buffer_size);
+    let write_body = |entry: &RGSSArchiveEntry, w: &mut BufWriter<Box<dyn Write>>| {
+        let path = dir_path.join(&entry.name);
+        let original = template.archive.find(&entry.name);
+        let reusable = match (original, template.checksums.get(&entry.name)) {
+            (Some(original), Some(&checksum))
+                if original.size == entry.size && original.magic == entry.magic =>
+            {
+                file_checksum(&path)? == checksum
+            }
+            _ => false,
+        };
+        match reusable {
+            true => {
+                log(
+                    verbosity,
+                    Verbosity::Verbose,
+                    format_args!("Reusing {}", entry.name),
+                );
+                original.unwrap().copy_raw(template.file, w)?;
+            }
+            false => {
+                log(
+                    verbosity,
+                    Verbosity::Normal,
+                    format_args!("Packing {}", entry.name),
+                );
+                entry.write_sequential(&mut buf, w, &mut File::open(path)?)?;
+            }
+        }
+        Ok(())
+    };
+    match template.keep_offsets {
+        true => archive.write_to_keeping_offsets(&mut file, write_body)?,
+        false => archive.write_to_with(&mut file, write_body)?,
+    }
+    file.flush()
+}
+
+/// Returns whether `a` and `b` name the same existing file.
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn ensure_file(path: impl AsRef<Path>, overwrite: bool) -> io::Result<File> {
     let path = path.as_ref();
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
     }
-    File::create(path)
+    if overwrite {
+        File::create(path)
+    } else {
+        OpenOptions::new().write(true).create_new(true).open(path)
+    }
+}
+
+/// Returns the file name of `path`, numbered as `name-2.ext`, `name-3.ext` and
+/// so on if an earlier entry already took it.
+fn flat_name(path: &Path, used: &mut HashSet<OsString>) -> OsString {
+    let name = path.file_name().unwrap_or_default().to_owned();
+    if used.insert(name.clone()) {
+        return name;
+    }
+    let stem = path.file_stem().unwrap_or_default();
+    for n in 2.. {
+        let mut name = stem.to_owned();
+        name.push(format!("-{}", n));
+        if let Some(extension) = path.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        if used.insert(name.clone()) {
+            return name;
+        }
+    }
+    unreachable!()
+}
+
+/// Writes the selected entries to `w` as a tar stream, preceded by an entry
+/// for every directory that leads to them.
+fn write_tar(
+    targets: &[(&RGSSArchiveEntry, PathBuf)],
+    file: &mut Input,
+    w: impl Write,
+) -> io::Result<()> {
+    let mut tar = tar::Builder::new(w);
+    let mut dirs = HashSet::new();
+    for (entry, path) in targets {
+        let mut ancestors: Vec<_> = path
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect();
+        ancestors.reverse();
+        for dir in ancestors {
+            if dirs.insert(dir.to_owned()) {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                tar.append_data(&mut header, dir, io::empty())?;
+            }
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_size(entry.size as u64);
+        tar.append_data(&mut header, path, entry.reader(&mut *file)?)?;
+    }
+    tar.into_inner()?.flush()
+}
+
+/// Compares the decrypted bodies of two entries, stopping at the first chunk
+/// that differs.
+fn same_body(
+    a: &RGSSArchiveEntry,
+    a_file: &mut Input,
+    b: &RGSSArchiveEntry,
+    b_file: &mut Input,
+) -> io::Result<bool> {
+    if a.size != b.size {
+        return Ok(false);
+    }
+    same_contents(a.reader(a_file)?, b.reader(b_file)?, a.size)
+}
+
+/// Compares the first `size` bytes of two readers, stopping at the first
+/// chunk that differs.
+fn same_contents(mut a: impl Read, mut b: impl Read, size: u32) -> io::Result<bool> {
+    let mut a_buf = vec![0; 8192];
+    let mut b_buf = vec![0; 8192];
+    let mut remaining = size as usize;
+    while remaining > 0 {
+        let len = remaining.min(a_buf.len());
+        a.read_exact(&mut a_buf[..len])?;
+        b.read_exact(&mut b_buf[..len])?;
+        if a_buf[..len] != b_buf[..len] {
+            return Ok(false);
+        }
+        remaining -= len;
+    }
+    Ok(true)
+}
+
+/// Lists the files in `dir_path` named by the entries of `archive` whose
+/// contents are identical, along with the space the extra copies take up.
+/// Files are grouped by a hash of their contents, and files with the same
+/// hash are compared byte for byte before being reported.
+///
+/// Nothing is deduplicated. v1/v2 store every body right after its record.
+/// v3 offsets are absolute, so two entries could point at one body if they
+/// also shared a key, but bodies are always written one after another and
+/// `verify` reports any that overlap.
+fn report_duplicates(archive: &RGSSArchive, dir_path: &Path) -> io::Result<()> {
+    let mut by_content = BTreeMap::new();
+    for entry in archive {
+        let mut hasher = DefaultHasher::new();
+        let mut file = File::open(dir_path.join(&entry.name))?;
+        let mut buf = [0; 8192];
+        loop {
+            match file.read(&mut buf)? {
+                0 => break,
+                read => hasher.write(&buf[..read]),
+            }
+        }
+        by_content
+            .entry((entry.size, hasher.finish()))
+            .or_insert_with(Vec::new)
+            .push(&*entry.name);
+    }
+    let mut wasted = 0;
+    for ((size, _), names) in by_content {
+        // Each copy joins the first group whose files it matches.
+        let mut groups: Vec<Vec<&str>> = Vec::new();
+        for name in names {
+            let mut group = None;
+            for (i, other) in groups.iter().enumerate() {
+                if same_contents(
+                    File::open(dir_path.join(other[0]))?,
+                    File::open(dir_path.join(name))?,
+                    size,
+                )? {
+                    group = Some(i);
+                    break;
+                }
+            }
+            match group {
+                Some(i) => groups[i].push(name),
+                None => groups.push(vec![name]),
+            }
+        }
+        for names in groups.iter().filter(|names| names.len() > 1) {
+            eprintln!("Duplicate content: {}", names.join(", "));
+            wasted += size as u64 * (names.len() as u64 - 1);
+        }
+    }
+    eprintln!("{} bytes in duplicate copies", wasted);
+    Ok(())
+}
+
+/// Adds an entry for every file under `dir_path`, sorted by name with ASCII
+/// case ignored.
+fn add_files(archive: &mut RGSSArchive, dir_path: &Path, follow_links: bool) -> io::Result<()> {
+    // Links are only followed while they stay inside the directory, so
+    // that packing cannot pick up unrelated files.
+    let root = fs::canonicalize(dir_path)?;
+    for entry in WalkDir::new(dir_path)
+        .follow_links(follow_links)
+        .sort_by_key(|entry| entry.file_name().to_ascii_uppercase())
+        .into_iter()
+        .filter_entry(|entry| {
+            if !follow_links || !entry.path_is_symlink() {
+                return true;
+            }
+            match fs::canonicalize(entry.path()) {
+                Ok(target) if target.starts_with(&root) => true,
+                _ => {
+                    eprintln!(
+                        "Skipping {}: link points outside the directory",
+                        entry.path().display()
+                    );
+                    false
+                }
+            }
+        })
+    {
+        let entry = match entry {
+            Err(e) if e.loop_ancestor().is_some() => {
+                eprintln!(
+                    "Skipping {}: link cycle",
+                    e.path().unwrap_or(dir_path).display()
+                );
+                continue;
+            }
+            entry => entry?,
+        };
+        if entry.file_type().is_file() {
+            archive.entries.push(RGSSArchiveEntry {
+                name: entry_name(entry.path().strip_prefix(dir_path).unwrap())?,
+                size: entry_size(entry.path(), entry.metadata()?.len())?,
+                offset: 0,
+                magic: 0,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Converts a file length into an entry size, which the format stores in 32
+/// bits.
+fn entry_size(path: &Path, len: u64) -> io::Result<u32> {
+    len.try_into().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: {}", E_FILE_TOO_LARGE, path.display()),
+        )
+    })
+}
+
+/// Updates the size of every entry of `archive` from the file of the same
+/// name under `dir_path`. Entries whose file is missing are dropped if
+/// `allow_missing` is set and are an error otherwise.
+fn refresh_sizes(
+    archive: &mut RGSSArchive,
+    dir_path: &Path,
+    allow_missing: bool,
+) -> io::Result<()> {
+    let mut missing = Vec::new();
+    for entry in &mut archive.entries {
+        let path = dir_path.join(&entry.name);
+        match fs::metadata(&path) {
+            Ok(metadata) => entry.size = entry_size(&path, metadata.len())?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => missing.push(entry.name.clone()),
+            Err(e) => return Err(e),
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    if !allow_missing {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{}: {}", E_MISSING_FILES, missing.join(", ")),
+        ));
+    }
+    for name in &missing {
+        eprintln!("Dropping {}: file is missing", name);
+    }
+    archive
+        .entries
+        .retain(|entry| !missing.contains(&entry.name));
+    Ok(())
+}
+
+fn random_magic() -> u32 {
+    loop {
+        let magic = RandomState::new().build_hasher().finish() as u32;
+        if magic != 0 {
+            return magic;
+        }
+    }
+}
+
+/// How much the CLI reports about each file, set by `--quiet` and
+/// `--verbose`. Errors and warnings are printed regardless.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+/// Prints a message about a single file to stderr if `verbosity` is at least
+/// `level`, keeping stdout free for listings and piped data.
+fn log(verbosity: Verbosity, level: Verbosity, message: impl fmt::Display) {
+    if verbosity >= level {
+        eprintln!("{}", message);
+    }
+}
+
+fn show_progress(verb: &'static str, verbosity: Verbosity) -> impl FnMut(&str, u64, u64) {
+    let mut shown = None;
+    move |name, done, total| {
+        if verbosity == Verbosity::Quiet {
+            return;
+        }
+        let percent = (done * 100).checked_div(total).unwrap_or(100);
+        if shown != Some(percent) {
+            shown = Some(percent);
+            eprint!("\r{} {} {}%", verb, name, percent);
+        }
+        if done == total {
+            match verbosity {
+                Verbosity::Verbose => eprintln!(" ({} bytes)", total),
+                _ => eprintln!(),
+            }
+            shown = None;
+        }
+    }
+}
+
+fn print_table(entries: &[RGSSArchiveEntry]) {
+    for entry in entries {
+        println!("{}", entry);
+    }
+}
+
+fn print_json(entries: &[RGSSArchiveEntry]) -> io::Result<()> {
+    println!("{}", serde_json::to_string_pretty(entries)?);
+    Ok(())
+}
+
+/// Writes a sidecar file such as a manifest, the table of an archive without
+/// its bodies that `pack --manifest` rebuilds the same archive from.
+fn write_json<T: serde::Serialize>(value: &T, path: &str) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut file, value)?;
+    file.flush()
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &str) -> io::Result<T> {
+    Ok(serde_json::from_reader(io::BufReader::new(File::open(
+        path,
+    )?))?)
+}
+
+/// Modification times of packed files by entry name, as seconds and
+/// nanoseconds since the Unix epoch. The format stores no times, so `pack`
+/// can save them to a sidecar file for `unpack` to restore.
+type Timestamps = BTreeMap<String, (u64, u32)>;
+
+fn collect_timestamps(archive: &RGSSArchive, dir_path: &Path) -> io::Result<Timestamps> {
+    let mut timestamps = Timestamps::new();
+    for entry in archive {
+        let modified = fs::metadata(dir_path.join(&entry.name))?.modified()?;
+        // Times before the epoch are rare enough to be left out.
+        if let Ok(since) = modified.duration_since(UNIX_EPOCH) {
+            timestamps.insert(entry.name.clone(), (since.as_secs(), since.subsec_nanos()));
+        }
+    }
+    Ok(timestamps)
+}
+
+fn restore_timestamp(file: &File, timestamps: &Timestamps, name: &str) -> io::Result<()> {
+    match timestamps.get(name) {
+        Some(&(secs, nanos)) => file.set_modified(UNIX_EPOCH + Duration::new(secs, nanos)),
+        None => Ok(()),
+    }
+}
+
+fn main() {
+    // The default report would print the `Debug` form of the error, which
+    // buries the message that names the file or entry.
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
 }
 
-fn main() -> io::Result<()> {
-    let args: Vec<String> = std::env::args().collect();
+fn run() -> io::Result<()> {
+    let mut args: Vec<String> = std::env::args().collect();
+    let encoding = take_option(&mut args, "--encoding")?
+        .map(|s| parse_encoding(&s))
+        .transpose()?
+        .unwrap_or_default();
+    let buffer_size = take_option(&mut args, "--buffer-size")?
+        .map(|s| s.parse::<usize>())
+        .transpose()
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: {}", E_INVALID_BUFFER_SIZE, e),
+            )
+        })?
+        .unwrap_or(8192);
+    let verbosity = match (
+        take_flag(&mut args, "--quiet"),
+        take_flag(&mut args, "--verbose"),
+    ) {
+        (true, _) => Verbosity::Quiet,
+        (false, true) => Verbosity::Verbose,
+        (false, false) => Verbosity::Normal,
+    };
+    let read_options = ReadOptions {
+        encoding,
+        ..ReadOptions::default()
+    };
     match args.get(1).map(String::as_str) {
         Some("help") => {
             print!("{}", USAGE);
@@ -41,55 +832,218 @@ fn main() -> io::Result<()> {
             println!("{}", VERSION);
         }
         Some("list") => {
+            let json = take_flag(&mut args, "--json");
             assert!(args.len() <= 3);
-            let archive_path = Path::new(&args[2]);
-            let mut archive = RGSSArchive::default();
-            {
-                let mut file = File::open(archive_path)?;
-                archive.read_header(&mut file)?;
-                archive.read_entries(&mut file)?;
+            let archive = RGSSArchive::open_with(&mut open_archive(&args[2])?, &read_options)?;
+            if json {
+                return print_json(&archive.entries);
             }
-            for entry in &archive.entries {
-                println!(
-                    "{}: {{ size: {}, offset: {}, magic: {} }}",
-                    entry.name, entry.size, entry.offset, entry.magic
-                );
+            print_table(&archive.entries);
+            if verbosity == Verbosity::Verbose {
+                println!("{} entries, {} bytes", archive.len(), archive.total_size());
             }
         }
         Some("unpack") => {
-            assert!(args.len() <= 5);
-            let archive_path = Path::new(&args[2]);
-            let dir_path = Path::new(&args[3]);
-            let filter = args
-                .get(4)
-                .map(|s| Regex::new(s))
+            let dry_run = take_flag(&mut args, "--dry-run");
+            let overwrite = take_flag(&mut args, "--overwrite");
+            let resume = take_flag(&mut args, "--resume");
+            let flatten = take_flag(&mut args, "--flatten");
+            let strip_components = take_option(&mut args, "--strip-components")?
+                .map(|s| s.parse::<usize>())
                 .transpose()
                 .map_err(|e| {
                     io::Error::new(
                         io::ErrorKind::InvalidInput,
-                        format!("{}: {}", E_INVALID_REGEX_FILTER, e),
+                        format!("{}: {}", E_INVALID_STRIP_COMPONENTS, e),
                     )
-                })?;
-            let mut archive = RGSSArchive::default();
+                })?
+                .unwrap_or(0);
+            let glob = take_option(&mut args, "--glob")?;
+            let tar = take_flag(&mut args, "--tar");
+            let exclude = take_option(&mut args, "--exclude")?
+                .map(|s| parse_regex(&s))
+                .transpose()?;
+            let manifest = take_option(&mut args, "--manifest")?;
+            let timestamps: Timestamps = take_option(&mut args, "--timestamps-from")?
+                .map(|path| read_json(&path))
+                .transpose()?
+                .unwrap_or_default();
+            let skip_empty = take_flag(&mut args, "--skip-empty");
+            let min_size = take_option(&mut args, "--min-size")?
+                .map(|s| parse_size(&s))
+                .transpose()?
+                .unwrap_or(0)
+                .max(skip_empty as u64);
+            let max_size = take_option(&mut args, "--max-size")?
+                .map(|s| parse_size(&s))
+                .transpose()?
+                .unwrap_or(u64::MAX);
+            let listed = take_option(&mut args, "--filter-from")?
+                .map(read_name_list)
+                .transpose()?;
+            let compression = take_option(&mut args, "--compress")?
+                .map(|s| parse_compression(&s))
+                .transpose()?;
+            if compression.is_some() && (resume || tar) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    E_CONFLICTING_COMPRESSION,
+                ));
+            }
+            assert!(args.len() <= 5);
+            // A tar stream has no output directory, so the names are kept
+            // relative and the filter moves up one place.
+            let (dir_path, filter) = match tar {
+                true => (Path::new(""), args.get(3)),
+                false => (Path::new(&args[3]), args.get(4)),
+            };
+            let filter: Box<dyn Fn(&str) -> bool> = match (filter, glob) {
+                (Some(_), Some(_)) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        E_CONFLICTING_FILTERS,
+                    ));
+                }
+                (Some(s), None) => {
+                    let re = parse_regex(s)?;
+                    Box::new(move |name| re.is_match(name))
+                }
+                (None, Some(s)) => {
+                    let glob = Glob::new(&s)
+                        .map_err(|e| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!("{}: {}", E_INVALID_GLOB_FILTER, e),
+                            )
+                        })?
+                        .compile_matcher();
+                    Box::new(move |name| glob.is_match(name))
+                }
+                (None, None) => Box::new(|_| true),
+            };
+            let filter: Box<dyn Fn(&str) -> bool> = match exclude {
+                Some(re) => Box::new(move |name| filter(name) && !re.is_match(name)),
+                None => filter,
+            };
+            let filter: Box<dyn Fn(&str) -> bool> = match &listed {
+                Some(names) => {
+                    let names: HashSet<_> = names.iter().cloned().collect();
+                    Box::new(move |name| filter(name) && names.contains(name))
+                }
+                None => filter,
+            };
             {
-                let mut file = File::open(archive_path)?;
-                archive.read_header(&mut file)?;
-                archive.read_entries(&mut file)?;
-                let mut buf = vec![0; 8192];
+                let mut file = open_archive(&args[2])?;
+                let archive = RGSSArchive::open_with(&mut file, &read_options)?;
+                for name in listed.iter().flatten() {
+                    if archive.find(name).is_none() {
+                        eprintln!("Not in the archive: {}", name);
+                    }
+                }
+                let mut targets = Vec::new();
+                let mut used = HashSet::new();
                 for entry in &archive.entries {
-                    if matches!(filter, Some(ref re) if !re.is_match(&entry.name)) {
-                        continue;
+                    if filter(&entry.name) && (min_size..=max_size).contains(&(entry.size as u64)) {
+                        let mut path = sanitize_name(&entry.name)?;
+                        if strip_components > 0 {
+                            if path.components().count() <= strip_components {
+                                eprintln!("Skipping {}: not enough components", entry.name);
+                                continue;
+                            }
+                            path = path.components().skip(strip_components).collect();
+                        }
+                        if flatten {
+                            path = PathBuf::from(flat_name(&path, &mut used));
+                        }
+                        if let Some(compression) = compression {
+                            let mut name = path.into_os_string();
+                            name.push(".");
+                            name.push(compression.extension());
+                            path = PathBuf::from(name);
+                        }
+                        targets.push((entry, dir_path.join(path)));
+                    }
+                }
+                if dry_run {
+                    for (_, path) in &targets {
+                        println!("{}", path.display());
+                    }
+                    return Ok(());
+                }
+                if let Some(manifest) = &manifest {
+                    write_json(&archive, manifest)?;
+                }
+                if tar {
+                    return write_tar(&targets, &mut file, io::stdout().lock());
+                }
+                if !overwrite && !resume {
+                    let existing: Vec<_> = targets
+                        .iter()
+                        .filter(|(_, path)| path.exists())
+                        .map(|(_, path)| path.display().to_string())
+                        .collect();
+                    if !existing.is_empty() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{}: {}", E_FILES_EXIST, existing.join(", ")),
+                        ));
+                    }
+                }
+                let mut buf = codec_buffer(buffer_size);
+                for (entry, path) in targets {
+                    // A file shorter than the entry is taken to be an earlier,
+                    // interrupted extraction. It is cut back to a whole number
+                    // of words, where the keystream can be picked up again.
+                    let done = match fs::metadata(&path) {
+                        Ok(metadata) if resume => Some(metadata.len()),
+                        _ => None,
+                    };
+                    match done {
+                        Some(done) if done == entry.size as u64 => continue,
+                        Some(done) if done < entry.size as u64 => {
+                            let start = done - done % 4;
+                            let mut out = OpenOptions::new().write(true).open(&path)?;
+                            out.set_len(start)?;
+                            out.seek(SeekFrom::End(0))?;
+                            log(
+                                verbosity,
+                                Verbosity::Normal,
+                                format_args!("Resuming {} at {}", entry.name, start),
+                            );
+                            entry.read_from(&mut buf, &mut file, &mut out, start as u32)?;
+                            restore_timestamp(&out, &timestamps, &entry.name)?;
+                            continue;
+                        }
+                        _ => {}
                     }
-                    println!("Unpacking {}", entry.name);
-                    entry.read(
-                        &mut buf,
-                        &mut file,
-                        &mut ensure_file(dir_path.join(&entry.name))?,
-                    )?;
+                    log(
+                        verbosity,
+                        Verbosity::Verbose,
+                        format_args!("{} is at offset {}", entry.name, entry.offset),
+                    );
+                    let mut out = ensure_file(path, overwrite || resume)?;
+                    let progress = show_progress("Unpacking", verbosity);
+                    // The decrypted body is fed straight into the encoder.
+                    let out = match compression {
+                        Some(compression) => compression.encode(out, |mut w| {
+                            Ok(entry.read_with_progress(&mut buf, &mut file, &mut w, progress)?)
+                        })?,
+                        None => {
+                            entry.read_with_progress(&mut buf, &mut file, &mut out, progress)?;
+                            out
+                        }
+                    };
+                    restore_timestamp(&out, &timestamps, &entry.name)?;
                 }
             }
         }
         Some("pack") => {
+            let dry_run = take_flag(&mut args, "--dry-run");
+            let follow_links = !take_flag(&mut args, "--no-follow-links");
+            let find_duplicates = take_flag(&mut args, "--find-duplicates");
+            let skip_empty = take_flag(&mut args, "--skip-empty");
+            let manifest = take_option(&mut args, "--manifest")?;
+            let save_timestamps = take_option(&mut args, "--save-timestamps")?;
             assert!(args.len() <= 5);
             let dir_path = Path::new(&args[2]);
             let archive_path = Path::new(&args[3]);
@@ -99,82 +1053,244 @@ fn main() -> io::Result<()> {
                     format!("{}: {}", E_INVALID_VERSION, e),
                 )
             })?;
-            let mut archive = RGSSArchive {
-                version: version.unwrap_or_else(|| {
-                    match archive_path.extension().and_then(OsStr::to_str) {
-                        Some("rgss3a") => 3,
-                        Some("rgss2a") => 2,
-                        _ => 1,
+            let mut archive = match manifest {
+                Some(manifest) => {
+                    let mut archive: RGSSArchive = read_json(&manifest)?;
+                    refresh_sizes(&mut archive, dir_path, false)?;
+                    archive
+                }
+                None => {
+                    let mut archive = RGSSArchive {
+                        version: version.unwrap_or_else(|| {
+                            archive_path
+                                .extension()
+                                .and_then(OsStr::to_str)
+                                .and_then(RGSSArchive::version_for_extension)
+                                .unwrap_or(1)
+                        }),
+                        encoding,
+                        ..RGSSArchive::default()
+                    };
+                    if archive.version == 3 {
+                        archive = archive.with_magic(random_magic());
                     }
-                }),
-                ..RGSSArchive::default()
-            };
-            for entry in WalkDir::new(dir_path)
-                .follow_links(true)
-                .sort_by_key(|entry| entry.file_name().to_ascii_uppercase())
-            {
-                let entry = entry?;
-                if entry.file_type().is_file() {
-                    archive.entries.push(RGSSArchiveEntry {
-                        name: entry
-                            .path()
-                            .strip_prefix(dir_path)
-                            .unwrap()
-                            .to_str()
-                            .unwrap()
-                            .to_owned(),
-                        size: entry.metadata()?.len().try_into().unwrap(),
-                        offset: 0,
-                        magic: 0,
-                    });
+                    add_files(&mut archive, dir_path, follow_links)?;
+                    archive
                 }
+            };
+            // Empty entries are valid in every version; this only drops
+            // placeholder files that are not wanted in the archive.
+            if skip_empty {
+                archive.entries.retain(|entry| entry.size > 0);
             }
-            {
-                let mut file = File::create(archive_path)?;
-                archive.write_header(&mut file)?;
-                archive.write_entries(&mut file)?;
-                let mut buf = vec![0; 8192];
-                for entry in &archive.entries {
-                    println!("Packing {}", entry.name);
-                    entry.write(
-                        &mut buf,
-                        &mut file,
-                        &mut File::open(dir_path.join(&entry.name))?,
-                    )?;
-                }
+            if find_duplicates {
+                report_duplicates(&archive, dir_path)?;
+            }
+            if dry_run {
+                archive.write_entries(&mut io::sink())?;
+                print_table(&archive.entries);
+                return Ok(());
+            }
+            if let Some(save_timestamps) = &save_timestamps {
+                write_json(&collect_timestamps(&archive, dir_path)?, save_timestamps)?;
             }
+            write_archive(&mut archive, dir_path, &args[3], buffer_size, verbosity)?;
         }
         Some("repack") => {
+            let add_new = take_flag(&mut args, "--add-new");
+            let allow_missing = take_flag(&mut args, "--allow-missing");
+            let preserve_order = take_flag(&mut args, "--preserve-order");
+            let keep_offsets = take_flag(&mut args, "--keep-offsets");
+            let checksums = take_option(&mut args, "--checksums")?
+                .map(read_checksums)
+                .transpose()?;
             assert!(args.len() <= 5);
             let dir_path = Path::new(&args[2]);
-            let archive_path = Path::new(&args[3]);
-            let template_path = Path::new(&args[4]);
-            let mut archive = RGSSArchive::default();
-            {
-                let mut file = File::open(template_path)?;
-                archive.read_header(&mut file)?;
-                archive.read_entries(&mut file)?;
+            if checksums.is_some() && same_file(&args[3], &args[4]) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    E_OUTPUT_IS_TEMPLATE,
+                ));
+            }
+            // Keeping the template's order keeps its offsets too, up to the
+            // first file whose size changed.
+            let mut template_file = open_archive(&args[4])?;
+            let mut archive = RGSSArchive::open_with(&mut template_file, &read_options)?;
+            let template = archive.clone();
+            refresh_sizes(&mut archive, dir_path, allow_missing)?;
+            if keep_offsets {
+                // A body whose size changed no longer fits where it was, so
+                // it is moved past the others.
                 for entry in &mut archive.entries {
-                    entry.size = fs::metadata(dir_path.join(&entry.name))?
-                        .len()
-                        .try_into()
-                        .unwrap();
+                    if template.find(&entry.name).map(|original| original.size) != Some(entry.size)
+                    {
+                        entry.offset = 0;
+                    }
                 }
             }
-            {
-                let mut file = File::create(archive_path)?;
-                archive.write_header(&mut file)?;
-                archive.write_entries(&mut file)?;
-                let mut buf = vec![0; 8192];
-                for entry in &archive.entries {
-                    println!("Packing {}", entry.name);
-                    entry.write(
-                        &mut buf,
-                        &mut file,
-                        &mut File::open(dir_path.join(&entry.name))?,
-                    )?;
+            if add_new {
+                // New files go after the template's entries. They have no key
+                // yet, so one is assigned when the archive is written.
+                let mut found = RGSSArchive::default();
+                add_files(&mut found, dir_path, true)?;
+                for entry in found.entries {
+                    if archive.find(&entry.name).is_none() {
+                        archive.entries.push(entry);
+                    }
+                }
+            }
+            if let Some(name) = archive.find_reordered(&template).filter(|_| preserve_order) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", E_REORDERED, name),
+                ));
+            }
+            match (&checksums, keep_offsets) {
+                (None, false) => {
+                    write_archive(&mut archive, dir_path, &args[3], buffer_size, verbosity)?
+                }
+                (checksums, _) => write_archive_reusing(
+                    &mut archive,
+                    dir_path,
+                    &args[3],
+                    buffer_size,
+                    verbosity,
+                    Template {
+                        archive: &template,
+                        file: &mut template_file,
+                        checksums: checksums.as_ref().unwrap_or(&HashMap::new()),
+                        keep_offsets,
+                    },
+                )?,
+            }
+        }
+        Some("verify") => {
+            assert!(args.len() <= 3);
+            let mut file = open_archive(&args[2])?;
+            let archive = RGSSArchive::open_with(&mut file, &read_options)?;
+            let issues = archive.verify(&mut file)?;
+            for issue in &issues {
+                println!("{}", issue);
+            }
+            if !issues.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", E_VERIFY_FAILED, issues.len()),
+                ));
+            }
+        }
+        Some("cat") => {
+            assert!(args.len() <= 5);
+            let name = &args[3];
+            let mut file = open_archive(&args[2])?;
+            let archive = RGSSArchive::open_with(&mut file, &read_options)?;
+            let mut output = create_output(args.get(4).map_or("-", String::as_str))?;
+            if !archive.extract(name, &mut codec_buffer(buffer_size), &mut file, &mut output)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{}: {}", E_ENTRY_NOT_FOUND, name),
+                ));
+            }
+        }
+        Some("checksum") => {
+            assert!(args.len() <= 3);
+            let mut file = open_archive(&args[2])?;
+            let archive = RGSSArchive::open_with(&mut file, &read_options)?;
+            let mut buf = codec_buffer(buffer_size);
+            for entry in &archive.entries {
+                println!(
+                    "{} {:08x}",
+                    entry.name,
+                    entry.checksum(&mut buf, &mut file)?
+                );
+            }
+        }
+        Some("diff") => {
+            assert!(args.len() <= 4);
+            let mut a_file = open_archive(&args[2])?;
+            let a = RGSSArchive::open_with(&mut a_file, &read_options)?;
+            let mut b_file = open_archive(&args[3])?;
+            let b = RGSSArchive::open_with(&mut b_file, &read_options)?;
+            // One line per entry, prefixed with `-` if it is only in the first
+            // archive, `+` if it is only in the second and `~` if it differs.
+            for a_entry in &a.entries {
+                match b.find(&a_entry.name) {
+                    None => println!("- {}", a_entry.name),
+                    Some(b_entry) => {
+                        if !same_body(a_entry, &mut a_file, b_entry, &mut b_file)? {
+                            println!("~ {}", a_entry.name);
+                        }
+                    }
                 }
             }
+            for b_entry in &b.entries {
+                if a.find(&b_entry.name).is_none() {
+                    println!("+ {}", b_entry.name);
+                }
+            }
+        }
+        Some("info") => {
+            assert!(args.len() <= 3);
+            let archive = RGSSArchive::open_with(&mut open_archive(&args[2])?, &read_options)?;
+            println!("Version: {}", archive.version);
+            println!("Entries: {}", archive.len());
+            println!("Size:    {}", archive.total_size());
+            println!("Table:   {}", archive.table_size()?);
+            let mut by_extension = BTreeMap::new();
+            for entry in &archive {
+                let extension = match Path::new(&entry.name).extension() {
+                    Some(extension) => format!(".{}", extension.to_string_lossy().to_lowercase()),
+                    None => "(none)".to_owned(),
+                };
+                let (count, size) = by_extension.entry(extension).or_insert((0, 0));
+                *count += 1;
+                *size += entry.size as u64;
+            }
+            let width = by_extension.keys().map(String::len).fold(9, usize::max);
+            println!(
+                "{:<width$} {:>8} {:>12}",
+                "Extension",
+                "Count",
+                "Bytes",
+                width = width
+            );
+            for (extension, (count, size)) in &by_extension {
+                println!(
+                    "{:<width$} {:>8} {:>12}",
+                    extension,
+                    count,
+                    size,
+                    width = width
+                );
+            }
+        }
+        Some("decrypt") => {
+            // Applies the body keystream to stdin: the first 4 bytes are XORed
+            // with the key, and it steps after every whole word. The cipher
+            // is its own inverse, so this encrypts just as well.
+            let magic = take_option(&mut args, "--magic")?;
+            let size = take_option(&mut args, "--size")?;
+            assert!(args.len() <= 2);
+            let (magic, size) = match (magic, size) {
+                (Some(magic), Some(size)) => (magic, size),
+                (None, _) => return Err(missing_option("--magic")),
+                (_, None) => return Err(missing_option("--size")),
+            };
+            let magic = parse_magic(&magic)?;
+            let size: u32 = parse_size(&size)?.try_into().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{}: {}", E_INVALID_SIZE, size),
+                )
+            })?;
+            let mut reader = DecryptReader::new(io::stdin().lock(), size, magic);
+            let copied = io::copy(&mut reader, &mut io::stdout().lock())?;
+            if copied < size as u64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("{}: {} of {} bytes", E_SHORT_INPUT, copied, size),
+                ));
+            }
         }
         _ => {
             print!("{}", USAGE);
@@ -0,0 +1,61 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::{run_codec, RGSSArchive, RGSSArchiveEntry, RgssError};
+
+/// An archive parsed from a memory-mapped file. Created by
+/// [`RGSSArchive::open_mmap`].
+///
+/// Bodies are decrypted straight from the mapping, so extracting an entry
+/// costs no seeks or reads. The file must not be truncated or modified while
+/// the mapping exists: on most platforms accessing a page past the new end of
+/// a shrunk file kills the process with `SIGBUS` or an access violation.
+#[derive(Debug)]
+pub struct MappedArchive {
+    pub archive: RGSSArchive,
+    map: Mmap,
+}
+
+impl RGSSArchive {
+    pub fn open_mmap(path: impl AsRef<Path>) -> Result<MappedArchive, RgssError> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is only read, and the caveats about concurrent
+        // modification are documented on `MappedArchive`.
+        let map = unsafe { Mmap::map(&file)? };
+        let archive = RGSSArchive::from_bytes(&map)?;
+        Ok(MappedArchive { archive, map })
+    }
+}
+
+impl MappedArchive {
+    /// Returns the whole mapped file.
+    pub fn bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Decrypts the body of `entry` from the mapping into `w`.
+    pub fn read(
+        &self,
+        buf: &mut [u8],
+        entry: &RGSSArchiveEntry,
+        w: &mut impl Write,
+    ) -> Result<(), RgssError> {
+        let body = self
+            .map
+            .get(entry.offset as usize..)
+            .and_then(|data| data.get(..entry.size as usize))
+            .ok_or(RgssError::TruncatedEntry)?;
+        run_codec(buf, &mut &body[..], w, entry.size, entry.magic, &mut |_| {})?;
+        Ok(())
+    }
+
+    /// Decrypts the named entry into a new buffer, or returns `None` if no
+    /// entry has that name.
+    pub fn extract(&self, name: &str) -> Option<Result<Vec<u8>, RgssError>> {
+        let entry = self.archive.find(name)?;
+        Some(entry.extract_to_vec(&self.map))
+    }
+}
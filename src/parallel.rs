@@ -0,0 +1,51 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{create_file, sanitize_name, RGSSArchive, RgssError};
+
+impl RGSSArchive {
+    /// Extracts every entry into `dir` using `threads` workers. Each worker
+    /// opens its own handle on the archive at `path`; entries are independent,
+    /// so no decryption state is shared. Returns the entries that could not be
+    /// extracted, in table order.
+    pub fn unpack_parallel(
+        &self,
+        path: &Path,
+        dir: &Path,
+        threads: usize,
+    ) -> Vec<(String, RgssError)> {
+        let next = AtomicUsize::new(0);
+        let errors = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for _ in 0..threads.max(1) {
+                scope.spawn(|| {
+                    let mut file = None;
+                    let mut buf = vec![0; 8192];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(entry) = self.entries.get(i) else {
+                            break;
+                        };
+                        let result = (|| {
+                            let file = match &mut file {
+                                Some(file) => file,
+                                None => file.insert(File::open(path)?),
+                            };
+                            let mut out = create_file(dir.join(sanitize_name(&entry.name)?))?;
+                            entry.read(&mut buf, file, &mut out)
+                        })();
+                        if let Err(e) = result {
+                            errors.lock().unwrap().push((i, entry.name.clone(), e));
+                        }
+                    }
+                });
+            }
+        });
+        let mut errors = errors.into_inner().unwrap();
+        errors.sort_by_key(|(i, _, _)| *i);
+        errors.into_iter().map(|(_, name, e)| (name, e)).collect()
+    }
+}
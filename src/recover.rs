@@ -0,0 +1,178 @@
+use std::io::{Read, Seek};
+use std::ops::Range;
+
+use crate::keystream::advance_magic;
+use crate::{RGSSArchive, RGSSArchiveEntry, ReadOptions, RgssError, RGSSAD_INITIAL_MAGIC};
+
+/// Longest name tried when searching for the next record. Real names are
+/// short relative paths, and every length tried costs a decryption attempt
+/// at every byte position.
+const RESYNC_MAX_NAME_LEN: u32 = 255;
+
+/// A v1/v2 record decoded from memory.
+struct Record {
+    /// Position of the record in the file.
+    start: u64,
+    name: String,
+    size: u32,
+    /// Position of the body in the file.
+    offset: u64,
+    /// Table key after the record, which is also the body key.
+    magic: u32,
+}
+
+impl Record {
+    fn end(&self) -> u64 {
+        self.offset + self.size as u64
+    }
+}
+
+/// Decodes the record at `position` of `data` with the table key `magic`,
+/// returning `None` unless it looks plausible: a non-empty name of at most
+/// `max_name_len` bytes that decodes without control characters, and a body
+/// that ends within `data`.
+fn decode_record(
+    data: &[u8],
+    position: u64,
+    mut magic: u32,
+    max_name_len: u32,
+    options: &ReadOptions,
+) -> Option<Record> {
+    let word = |at: u64| -> Option<u32> {
+        let at = usize::try_from(at).ok()?;
+        Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+    };
+    let name_len = word(position)? ^ advance_magic(&mut magic);
+    if name_len == 0 || name_len > max_name_len.min(options.max_name_len) {
+        return None;
+    }
+    let start = position as usize + 4;
+    let name = data.get(start..start + name_len as usize)?;
+    // Most candidates tried while resynchronizing fail here, before anything
+    // is allocated. Neither encoding uses these bytes inside other characters.
+    let mut name_magic = magic;
+    if name.iter().any(|b| {
+        let b = b ^ advance_magic(&mut name_magic) as u8;
+        b < 0x20 || b == 0x7f
+    }) {
+        return None;
+    }
+    let mut name = name.to_vec();
+    for b in name.iter_mut() {
+        *b ^= advance_magic(&mut magic) as u8;
+    }
+    let name = options.encoding.decode(name).ok()?;
+    if name.chars().any(char::is_control) {
+        return None;
+    }
+    let size = word(position + 4 + name_len as u64)? ^ advance_magic(&mut magic);
+    let record = Record {
+        start: position,
+        name,
+        size,
+        offset: position + 8 + name_len as u64,
+        magic,
+    };
+    (record.end() <= data.len() as u64).then_some(record)
+}
+
+/// Searches forward from `from` for a record that is either the last one in
+/// `data` or is followed by another plausible record. The table key at the
+/// record is unknown, so it is derived from every name length that could be
+/// stored there.
+fn resync(data: &[u8], from: u64, options: &ReadOptions) -> Option<Record> {
+    let word = |at: u64| u32::from_le_bytes(data[at as usize..at as usize + 4].try_into().unwrap());
+    // Stepping the key n times maps it to `a * key + c`, so the key of the
+    // size word can be found without stepping through the name. A size that
+    // runs past the end of the data rules out most candidates cheaply.
+    let mut steps = vec![(1u32, 0u32)];
+    for n in 0..=RESYNC_MAX_NAME_LEN as usize {
+        let (a, c) = steps[n];
+        steps.push((a.wrapping_mul(7), c.wrapping_mul(7).wrapping_add(3)));
+    }
+    let len = data.len() as u64;
+    for position in from..len.saturating_sub(8) {
+        let key_word = word(position);
+        for name_len in 1..=RESYNC_MAX_NAME_LEN {
+            let end = position + 8 + name_len as u64;
+            if end > len {
+                break;
+            }
+            let (a, c) = steps[name_len as usize + 1];
+            let size_key = a.wrapping_mul(key_word ^ name_len).wrapping_add(c);
+            if end + (word(end - 4) ^ size_key) as u64 > len {
+                continue;
+            }
+            let record = match decode_record(data, position, key_word ^ name_len, name_len, options)
+            {
+                Some(record) => record,
+                None => continue,
+            };
+            if record.end() == len
+                || decode_record(data, record.end(), record.magic, u32::MAX, options).is_some()
+            {
+                return Some(record);
+            }
+        }
+    }
+    None
+}
+
+impl RGSSArchive {
+    /// Best-effort counterpart to [`RGSSArchive::read_entries_with`] for
+    /// salvaging damaged v1/v2 archives. Where a record cannot be decoded,
+    /// the rest of the file is scanned for the next plausible record and
+    /// reading carries on from there. Returns the byte ranges that were
+    /// skipped; entries recovered after a skip may still be bogus.
+    ///
+    /// The rest of the file is read into memory. v3 archives are rejected
+    /// with [`RgssError::UnsupportedVersion`].
+    pub fn read_entries_lenient(
+        &mut self,
+        r: &mut (impl Read + Seek),
+        options: &ReadOptions,
+    ) -> Result<Vec<Range<u64>>, RgssError> {
+        if !matches!(self.version, 1 | 2) {
+            return Err(RgssError::UnsupportedVersion(self.version));
+        }
+        self.encoding = options.encoding;
+        let base = r.stream_position()?;
+        // Positions are kept relative to the file, so the header is left in
+        // place as padding.
+        let mut data = vec![0; base as usize];
+        r.read_to_end(&mut data)?;
+        let len = data.len() as u64;
+        let mut skipped = Vec::new();
+        let mut magic = RGSSAD_INITIAL_MAGIC;
+        let mut position = base;
+        while position < len {
+            let record = match decode_record(&data, position, magic, u32::MAX, options) {
+                Some(record) => record,
+                None => match resync(&data, position + 1, options) {
+                    Some(record) => {
+                        skipped.push(position..record.start);
+                        record
+                    }
+                    None => {
+                        skipped.push(position..len);
+                        break;
+                    }
+                },
+            };
+            options.check_entry_count(self.entries.len())?;
+            let offset = record
+                .offset
+                .try_into()
+                .map_err(|_| RgssError::OffsetOverflow(record.name.clone()))?;
+            position = record.end();
+            magic = record.magic;
+            self.entries.push(RGSSArchiveEntry {
+                name: record.name,
+                size: record.size,
+                offset,
+                magic: record.magic,
+            });
+        }
+        Ok(skipped)
+    }
+}
@@ -0,0 +1,100 @@
+use std::convert::TryInto;
+use std::io::{self, Read};
+
+use crate::{
+    read_record_rgssad, DecryptReader, RGSSArchiveEntry, ReadOptions, RgssError,
+    RGSSAD_INITIAL_MAGIC,
+};
+
+/// Sequential reader over the records of a v1/v2 archive. Created by
+/// [`RGSSArchive::stream_entries`](crate::RGSSArchive::stream_entries).
+pub struct EntryStream<R> {
+    reader: R,
+    version: u8,
+    options: ReadOptions,
+    magic: u32,
+    position: u64,
+    remaining: u64,
+    count: usize,
+}
+
+impl<R: Read> EntryStream<R> {
+    pub(crate) fn new(reader: R, version: u8, options: ReadOptions) -> Self {
+        EntryStream {
+            reader,
+            version,
+            options,
+            magic: RGSSAD_INITIAL_MAGIC,
+            position: 8,
+            remaining: 0,
+            count: 0,
+        }
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Advances to the next record. Any part of the previous body that was not
+    /// read is skipped. Fails with [`RgssError::TooManyEntries`] once more
+    /// records than [`ReadOptions::max_entries`] have been found.
+    pub fn next_entry(&mut self) -> Result<Option<StreamedEntry<'_, R>>, RgssError> {
+        if self.remaining > 0 {
+            let skipped = io::copy(
+                &mut (&mut self.reader).take(self.remaining),
+                &mut io::sink(),
+            )?;
+            self.position += skipped;
+            if skipped < self.remaining {
+                return Err(RgssError::TruncatedEntry);
+            }
+            self.remaining = 0;
+        }
+        let (name, size, header_len) = match read_record_rgssad(
+            &mut self.reader,
+            &mut self.magic,
+            &self.options,
+            self.position,
+        )? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        self.options.check_entry_count(self.count)?;
+        self.count += 1;
+        self.position += header_len;
+        let offset = self
+            .position
+            .try_into()
+            .map_err(|_| RgssError::OffsetOverflow(name.clone()))?;
+        self.remaining = size as u64;
+        Ok(Some(StreamedEntry {
+            entry: RGSSArchiveEntry {
+                name,
+                size,
+                offset,
+                magic: self.magic,
+            },
+            body: DecryptReader::new(&mut self.reader, size, self.magic),
+            remaining: &mut self.remaining,
+            position: &mut self.position,
+        }))
+    }
+}
+
+/// An entry produced by [`EntryStream::next_entry`]. Reading from it yields
+/// the decrypted body.
+pub struct StreamedEntry<'a, R> {
+    pub entry: RGSSArchiveEntry,
+    body: DecryptReader<&'a mut R>,
+    remaining: &'a mut u64,
+    position: &'a mut u64,
+}
+
+impl<R: Read> Read for StreamedEntry<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.body.read(buf)?;
+        *self.remaining -= read as u64;
+        *self.position += read as u64;
+        Ok(read)
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+use std::io::{self, Seek, SeekFrom};
+
+use crate::RGSSArchive;
+
+/// A problem found by [`RGSSArchive::verify`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VerifyIssue {
+    /// The body of the entry extends past the end of the file.
+    OutOfBounds {
+        name: String,
+        offset: u64,
+        size: u64,
+        len: u64,
+    },
+    /// The bodies of two entries share bytes.
+    Overlap { first: String, second: String },
+    /// The body of a v3 entry is empty and its offset points into the header
+    /// or table, where no body can start. Empty bodies take no part in the
+    /// overlap check, so a bogus offset would otherwise go unnoticed.
+    ZeroSize { name: String, offset: u64 },
+}
+
+impl fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyIssue::OutOfBounds {
+                name,
+                offset,
+                size,
+                len,
+            } => write!(
+                f,
+                "{}: body {}..{} is outside the file ({} bytes)",
+                name,
+                offset,
+                offset + size,
+                len
+            ),
+            VerifyIssue::Overlap { first, second } => {
+                write!(f, "{}: body overlaps {}", second, first)
+            }
+            VerifyIssue::ZeroSize { name, offset } => {
+                write!(f, "{}: empty body at {} is inside the table", name, offset)
+            }
+        }
+    }
+}
+
+impl RGSSArchive {
+    /// Checks every entry body against the length of the backing file and
+    /// against each other, collecting all problems found.
+    pub fn verify(&self, r: &mut impl Seek) -> io::Result<Vec<VerifyIssue>> {
+        let len = r.seek(SeekFrom::End(0))?;
+        // v1/v2 bodies start right after their record, so only v3 offsets
+        // can point back into the table.
+        let table_end = match self.version {
+            3 => self.table_size()?,
+            _ => 0,
+        };
+        let mut issues = Vec::new();
+        for entry in &self.entries {
+            let offset = entry.offset as u64;
+            let size = entry.size as u64;
+            if size == 0 && offset < table_end {
+                issues.push(VerifyIssue::ZeroSize {
+                    name: entry.name.clone(),
+                    offset,
+                });
+            }
+            if offset + size > len {
+                issues.push(VerifyIssue::OutOfBounds {
+                    name: entry.name.clone(),
+                    offset,
+                    size,
+                    len,
+                });
+            }
+        }
+        let mut by_offset: Vec<_> = self.entries.iter().filter(|e| e.size > 0).collect();
+        by_offset.sort_by_key(|entry| entry.offset);
+        let mut last: Option<(u64, &str)> = None;
+        for entry in by_offset {
+            let end = entry.offset as u64 + entry.size as u64;
+            match last {
+                Some((last_end, first)) if (entry.offset as u64) < last_end => {
+                    issues.push(VerifyIssue::Overlap {
+                        first: first.to_owned(),
+                        second: entry.name.clone(),
+                    });
+                    if end > last_end {
+                        last = Some((end, &entry.name));
+                    }
+                }
+                _ => last = Some((end, &entry.name)),
+            }
+        }
+        Ok(issues)
+    }
+}
@@ -0,0 +1,62 @@
+#![cfg(feature = "std")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rgssad::{RGSSArchive, RGSSArchiveEntry};
+
+/// Tracks the largest single allocation made while the tests run.
+struct Counting;
+
+static LARGEST: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LARGEST.fetch_max(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LARGEST.fetch_max(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+#[test]
+fn large_bodies_are_streamed() {
+    const SIZE: u32 = 16 << 20;
+    for version in 1..=3 {
+        let mut archive = RGSSArchive {
+            version,
+            ..RGSSArchive::default()
+        };
+        for name in ["a.bin", "b.bin"] {
+            archive.entries.push(RGSSArchiveEntry {
+                name: name.to_owned(),
+                size: SIZE,
+                ..RGSSArchiveEntry::default()
+            });
+        }
+        LARGEST.store(0, Ordering::Relaxed);
+        archive
+            .write_to(&mut [0; 8192], &mut io::sink(), |entry| {
+                Ok(io::repeat(0).take(entry.size as u64))
+            })
+            .unwrap();
+        let largest = LARGEST.load(Ordering::Relaxed);
+        assert!(
+            largest < 1 << 20,
+            "v{}: allocated {} bytes",
+            version,
+            largest
+        );
+    }
+}
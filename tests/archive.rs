@@ -0,0 +1,1869 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use std::fs;
+use std::io::{self, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use common::{body, sample, TempDir};
+use rgssad::{
+    codec_buffer, rgss3a_xor, rgssad_name_keystream, sanitize_name, ArchiveBuilder, EntryOrder,
+    Keystream, NameEncoding, RGSSArchive, RGSSArchiveEntry, ReadOptions, RgssError, VerifyIssue,
+    RGSSAD_INITIAL_MAGIC,
+};
+
+/// Returns a v1 header followed by a table record holding just a name length.
+fn name_len_record(name_len: u32) -> Vec<u8> {
+    let mut data = b"RGSSAD\0\x01".to_vec();
+    data.extend((name_len ^ RGSSAD_INITIAL_MAGIC).to_le_bytes());
+    data
+}
+
+#[test]
+fn huge_name_len_is_rejected_before_allocating() {
+    let data = name_len_record(0xffff_fff0);
+    assert!(matches!(
+        RGSSArchive::from_bytes(&data),
+        Err(RgssError::NameTooLong(0xffff_fff0))
+    ));
+    let options = ReadOptions {
+        max_name_len: 16,
+        ..ReadOptions::default()
+    };
+    let result = RGSSArchive::open_with(&mut Cursor::new(name_len_record(17)), &options);
+    assert!(matches!(result, Err(RgssError::NameTooLong(17))));
+}
+
+#[test]
+fn extract_range_matches_slice() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        for entry in &archive.entries {
+            let body = body(&entry.name);
+            for start in (0..=entry.size).step_by(4) {
+                for len in 0..=entry.size - start {
+                    let mut out = Vec::new();
+                    entry
+                        .extract_range(&mut [0; 8], &mut Cursor::new(&data), &mut out, start, len)
+                        .unwrap();
+                    assert_eq!(out, &body[start as usize..(start + len) as usize]);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn find_and_extract_by_name() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        let entry = archive.find("Graphics\\Titles/title.png").unwrap();
+        assert_eq!(entry.name, "Graphics/Titles/title.png");
+        assert!(archive.find("Graphics/Titles/missing.png").is_none());
+
+        let mut out = Vec::new();
+        let found = archive
+            .extract(
+                "Data/Map001.rxdata",
+                &mut [0; 16],
+                &mut Cursor::new(&data),
+                &mut out,
+            )
+            .unwrap();
+        assert!(found);
+        assert_eq!(out, body("Data/Map001.rxdata"));
+        let mut out = Vec::new();
+        let found = archive
+            .extract("missing", &mut [0; 16], &mut Cursor::new(&data), &mut out)
+            .unwrap();
+        assert!(!found);
+        assert!(out.is_empty());
+    }
+}
+
+#[test]
+fn sanitize_name_rejects_escaping_names() {
+    for name in [
+        "../escape.txt",
+        "Data/../../escape.txt",
+        "Data\\..\\..\\escape.txt",
+        "..",
+        "/etc/passwd",
+        "\\escape.txt",
+        "",
+        "./",
+    ] {
+        assert!(
+            matches!(sanitize_name(name), Err(RgssError::UnsafeName(_))),
+            "{:?}",
+            name
+        );
+    }
+    assert_eq!(
+        sanitize_name("Data\\./Map001.rxdata").unwrap(),
+        Path::new("Data").join("Map001.rxdata")
+    );
+}
+
+#[test]
+fn shift_jis_names_round_trip() {
+    let names = ["データ/マップ001.rxdata", "Graphics/タイトル.png"];
+    for version in 1..=3 {
+        let mut archive = RGSSArchive {
+            version,
+            encoding: NameEncoding::ShiftJis,
+            ..RGSSArchive::default()
+        };
+        for name in names {
+            archive
+                .push_entry(RGSSArchiveEntry {
+                    name: name.to_owned(),
+                    ..RGSSArchiveEntry::default()
+                })
+                .unwrap();
+        }
+        let data = archive
+            .write_to_vec(|name| name.as_bytes().to_vec())
+            .unwrap();
+
+        let options = ReadOptions {
+            encoding: NameEncoding::ShiftJis,
+            ..ReadOptions::default()
+        };
+        let read = RGSSArchive::open_with(&mut Cursor::new(&data), &options).unwrap();
+        assert_eq!(read.encoding, NameEncoding::ShiftJis);
+        let read_names: Vec<_> = read.entries.iter().map(|entry| &*entry.name).collect();
+        assert_eq!(read_names, names);
+        for entry in &read.entries {
+            assert_eq!(entry.extract_to_vec(&data).unwrap(), entry.name.as_bytes());
+        }
+        assert!(matches!(
+            RGSSArchive::from_bytes(&data),
+            Err(RgssError::InvalidName {
+                encoding: NameEncoding::Utf8,
+                ..
+            })
+        ));
+    }
+}
+
+/// Reads from `data` until `fail_at`, where every further read fails with
+/// something other than an end of file.
+struct FailingReader<'a> {
+    data: Cursor<&'a [u8]>,
+    fail_at: u64,
+}
+
+impl Read for FailingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.fail_at.saturating_sub(self.data.position());
+        if left == 0 {
+            return Err(io::Error::other("disk on fire"));
+        }
+        let len = buf.len().min(left as usize);
+        self.data.read(&mut buf[..len])
+    }
+}
+
+impl Seek for FailingReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.data.seek(pos)
+    }
+}
+
+#[test]
+fn read_errors_are_not_taken_for_the_end_of_the_table() {
+    for version in 1..=3 {
+        let (_, data) = sample(version);
+        let mut r = FailingReader {
+            data: Cursor::new(&data),
+            fail_at: 20,
+        };
+        match RGSSArchive::open(&mut r) {
+            Err(RgssError::Io(e)) => assert_eq!(e.to_string(), "disk on fire"),
+            result => panic!("v{}: {:?}", version, result),
+        }
+    }
+}
+
+#[test]
+fn truncated_archives_are_rejected() {
+    for version in 1..=2 {
+        let (archive, data) = sample(version);
+        assert_eq!(RGSSArchive::from_bytes(&data).unwrap().len(), 3);
+        // v1/v2 tables have no terminator, so a file cut right after a body
+        // is a valid archive with fewer entries.
+        let ends: Vec<_> = archive
+            .iter_with_offsets()
+            .map(|(_, range)| range.end as usize)
+            .collect();
+        for len in 9..data.len() {
+            if let Some(count) = ends.iter().position(|&end| end == len) {
+                assert_eq!(
+                    RGSSArchive::from_bytes(&data[..len]).unwrap().len(),
+                    count + 1
+                );
+                continue;
+            }
+            assert!(
+                matches!(
+                    RGSSArchive::from_bytes(&data[..len]),
+                    Err(RgssError::TruncatedEntry)
+                ),
+                "v{} cut at {}",
+                version,
+                len
+            );
+        }
+    }
+}
+
+#[test]
+fn decrypt_reader_matches_read_for_any_size() {
+    for size in 0..=13u32 {
+        let mut archive = RGSSArchive {
+            version: 1,
+            ..RGSSArchive::default()
+        };
+        archive.entries.push(RGSSArchiveEntry {
+            name: "body".to_owned(),
+            ..RGSSArchiveEntry::default()
+        });
+        let plain: Vec<u8> = (0..size as u8).collect();
+        let data = archive.write_to_vec(|_| plain.clone()).unwrap();
+        let entry = &archive.entries[0];
+
+        let mut expected = Vec::new();
+        entry
+            .read(&mut [0; 8], &mut Cursor::new(&data), &mut expected)
+            .unwrap();
+        assert_eq!(expected, plain);
+        // Reads of 3 bytes straddle word boundaries.
+        for chunk in [1, 3, 4, 5] {
+            let mut reader = entry.reader(Cursor::new(&data)).unwrap();
+            let mut out = Vec::new();
+            let mut buf = vec![0; chunk];
+            loop {
+                match reader.read(&mut buf).unwrap() {
+                    0 => break,
+                    n => out.extend_from_slice(&buf[..n]),
+                }
+            }
+            assert_eq!(out, expected, "size {} in chunks of {}", size, chunk);
+        }
+    }
+}
+
+#[test]
+fn verify_reports_truncated_archives() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        assert_eq!(archive.verify(&mut Cursor::new(&data)).unwrap(), []);
+        let title = archive.find("Graphics/Titles/title.png").unwrap();
+        let len = title.offset as u64 + 3;
+        let issues = archive
+            .verify(&mut Cursor::new(&data[..len as usize]))
+            .unwrap();
+        // The empty entry comes last, so it is out of bounds too.
+        let out_of_bounds: Vec<_> = issues
+            .iter()
+            .map(|issue| match issue {
+                VerifyIssue::OutOfBounds { name, len: l, .. } => {
+                    assert_eq!(*l, len);
+                    &**name
+                }
+                issue => panic!("{}", issue),
+            })
+            .collect();
+        assert_eq!(out_of_bounds, ["Graphics/Titles/title.png", "empty.txt"]);
+    }
+}
+
+#[test]
+fn verify_reports_empty_bodies_inside_the_table() {
+    let (mut archive, data) = sample(3);
+    archive.entries[2].offset = 4;
+    let issues = archive.verify(&mut Cursor::new(&data)).unwrap();
+    assert_eq!(
+        issues,
+        [VerifyIssue::ZeroSize {
+            name: "empty.txt".to_owned(),
+            offset: 4
+        }]
+    );
+    assert_eq!(
+        issues[0].to_string(),
+        "empty.txt: empty body at 4 is inside the table"
+    );
+    // Empty v1/v2 bodies sit between records and are never flagged.
+    let (archive, data) = sample(1);
+    assert_eq!(archive.verify(&mut Cursor::new(&data)).unwrap(), []);
+}
+
+#[test]
+fn v3_keeps_zero_keys_of_read_entries() {
+    let mut archive = RGSSArchive {
+        version: 3,
+        ..RGSSArchive::default()
+    }
+    .with_magic(0x1234_5678);
+    for name in ["a", "b"] {
+        archive.entries.push(RGSSArchiveEntry {
+            name: name.to_owned(),
+            ..RGSSArchiveEntry::default()
+        });
+    }
+    archive.write_to_vec(|_| b"body".to_vec()).unwrap();
+    // New entries get distinct keys stepped from the archive key.
+    assert_ne!(archive.entries[0].magic, 0);
+    assert_ne!(archive.entries[0].magic, archive.entries[1].magic);
+
+    archive.entries[0].magic = 0;
+    let data = archive.write_to_vec(|_| b"body".to_vec()).unwrap();
+    let read = RGSSArchive::from_bytes(&data).unwrap();
+    assert_eq!(read.entries[0].magic, 0);
+    assert_eq!(read.entries[0].extract_to_vec(&data).unwrap(), b"body");
+    let mut rewritten = read.clone();
+    rewritten.write_to_vec(|_| b"body".to_vec()).unwrap();
+    assert_eq!(rewritten, read);
+}
+
+#[test]
+fn v3_seeds_change_ciphertext_only() {
+    let write = |magic| {
+        let mut archive = RGSSArchive {
+            version: 3,
+            ..RGSSArchive::default()
+        }
+        .with_magic(magic);
+        archive.entries.push(RGSSArchiveEntry {
+            name: "Data/Map001.rxdata".to_owned(),
+            ..RGSSArchiveEntry::default()
+        });
+        archive.write_to_vec(|name| body(name).to_vec()).unwrap()
+    };
+    let a = write(1);
+    let b = write(0x1234_5678);
+    assert_eq!(a.len(), b.len());
+    assert_ne!(a[8..], b[8..]);
+    for data in [&a, &b] {
+        let archive = RGSSArchive::from_bytes(data).unwrap();
+        assert_eq!(
+            archive.entries[0].extract_to_vec(data).unwrap(),
+            body("Data/Map001.rxdata")
+        );
+    }
+    let body_at = |data: &[u8]| RGSSArchive::from_bytes(data).unwrap().entries[0].offset as usize;
+    assert_ne!(a[body_at(&a)..], b[body_at(&b)..]);
+}
+
+#[test]
+fn parallel_unpack_matches_sequential() {
+    let dir = TempDir::new();
+    let mut archive = RGSSArchive {
+        version: 1,
+        ..RGSSArchive::default()
+    };
+    for i in 0..40 {
+        archive.entries.push(RGSSArchiveEntry {
+            name: format!("Data/{:02}.bin", i),
+            ..RGSSArchiveEntry::default()
+        });
+    }
+    archive.entries.push(RGSSArchiveEntry {
+        name: "../escape.bin".to_owned(),
+        ..RGSSArchiveEntry::default()
+    });
+    let data = archive
+        .write_to_vec(|name| name.repeat(name.len() * 7).into_bytes())
+        .unwrap();
+    let path = dir.join("Game.rgssad");
+    fs::write(&path, &data).unwrap();
+
+    let errors = archive.unpack_parallel(&path, &dir.join("parallel"), 4);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, "../escape.bin");
+    assert!(matches!(errors[0].1, RgssError::UnsafeName(_)));
+    archive
+        .unpack_with(&mut Cursor::new(&data), &dir.join("sequential"), |name| {
+            sanitize_name(name).ok()
+        })
+        .unwrap();
+    for entry in &archive.entries[..40] {
+        let parallel = fs::read(dir.join("parallel").join(&entry.name)).unwrap();
+        let sequential = fs::read(dir.join("sequential").join(&entry.name)).unwrap();
+        assert_eq!(parallel.len(), entry.size as usize);
+        assert_eq!(parallel, sequential);
+    }
+}
+
+#[test]
+fn check_names_reports_every_collision() {
+    let mut archive = RGSSArchive {
+        version: 1,
+        ..RGSSArchive::default()
+    };
+    for name in ["Data/a", "b", "Data\\a", "c", "b", "Data/a"] {
+        archive.entries.push(RGSSArchiveEntry {
+            name: name.to_owned(),
+            ..RGSSArchiveEntry::default()
+        });
+    }
+    match archive.check_names() {
+        Err(RgssError::DuplicateNames(names)) => {
+            assert_eq!(names, ["Data/a", "Data\\a", "b", "b", "Data/a"])
+        }
+        result => panic!("{:?}", result),
+    }
+    assert!(archive.write_to_vec(|_| Vec::new()).is_err());
+    archive.entries.truncate(2);
+    assert!(archive.check_names().is_ok());
+}
+
+#[test]
+fn builder_normalizes_and_rejects_duplicate_names() {
+    let mut builder = ArchiveBuilder::new(1);
+    builder
+        .add_entry("Data\\Scripts.rxdata", &b"scripts"[..])
+        .unwrap();
+    let e = builder
+        .add_entry("Data/Scripts.rxdata", &b"again"[..])
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    let mut data = Vec::new();
+    let archive = builder.write(&mut data).unwrap();
+    assert_eq!(archive.len(), 1);
+    assert_eq!(archive.entries[0].name, "Data/Scripts.rxdata");
+    assert_eq!(
+        archive.entries[0].extract_to_vec(&data).unwrap(),
+        b"scripts"
+    );
+}
+
+#[test]
+fn v3_table_terminator_round_trips() {
+    for count in [0, 1, 2, 17] {
+        let mut archive = RGSSArchive {
+            version: 3,
+            ..RGSSArchive::default()
+        }
+        .with_magic(0xdead_beef);
+        for i in 0..count {
+            archive.entries.push(RGSSArchiveEntry {
+                name: format!("{}.txt", i),
+                ..RGSSArchiveEntry::default()
+            });
+        }
+        let data = archive
+            .write_to_vec(|name| name.as_bytes().to_vec())
+            .unwrap();
+        let read = RGSSArchive::from_bytes(&data).unwrap();
+        assert_eq!(read.len(), count);
+        assert_eq!(read, archive);
+        // The terminator is the single word right before the first body.
+        let table_end = archive.table_size().unwrap() as usize;
+        let xor = rgss3a_xor(0xdead_beef);
+        assert_eq!(data[table_end - 4..table_end], xor.to_le_bytes());
+    }
+}
+
+#[test]
+fn data_end_is_the_file_length() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        assert_eq!(archive.data_end(), data.len() as u64);
+        assert_eq!(
+            RGSSArchive::from_bytes(&data).unwrap().data_end(),
+            data.len() as u64
+        );
+
+        let mut empty = RGSSArchive {
+            version,
+            ..RGSSArchive::default()
+        };
+        let data = empty.write_to_vec(|_| Vec::new()).unwrap();
+        assert_eq!(empty.data_end(), data.len() as u64);
+    }
+}
+
+#[test]
+fn checksum_is_stable_and_tracks_contents() {
+    let checksums = |version, body: &'static [u8]| {
+        let mut archive = RGSSArchive {
+            version,
+            ..RGSSArchive::default()
+        };
+        archive.entries.push(RGSSArchiveEntry {
+            name: "a".to_owned(),
+            ..RGSSArchiveEntry::default()
+        });
+        let data = archive.write_to_vec(|_| body.to_vec()).unwrap();
+        archive.entries[0]
+            .checksum(&mut [0; 8], &mut Cursor::new(&data))
+            .unwrap()
+    };
+    for version in 1..=3 {
+        // The standard CRC-32 check value, whatever the key.
+        assert_eq!(checksums(version, b"123456789"), 0xcbf4_3926);
+        assert_eq!(checksums(version, b""), 0);
+        assert_ne!(checksums(version, b"123456780"), 0xcbf4_3926);
+    }
+}
+
+#[test]
+fn exposed_schedules_decode_written_tables() {
+    let word = |data: &[u8], at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+
+    let (archive, data) = sample(1);
+    let mut keys = rgssad_name_keystream(RGSSAD_INITIAL_MAGIC);
+    let name_len = word(&data, 8) ^ keys.next().unwrap();
+    let name: Vec<u8> = data[12..12 + name_len as usize]
+        .iter()
+        .zip(&mut keys)
+        .map(|(b, key)| b ^ key as u8)
+        .collect();
+    let size_at = 12 + name_len as usize;
+    let size = word(&data, size_at) ^ keys.next().unwrap();
+    let entry = &archive.entries[0];
+    // Names are stored with `\`.
+    assert_eq!(name, b"Data\\Map001.rxdata");
+    assert_eq!(size, entry.size);
+    assert_eq!(keys.next(), Some(entry.magic));
+
+    let (archive, data) = sample(3);
+    let xor = rgss3a_xor(word(&data, 8));
+    let entry = &archive.entries[0];
+    assert_eq!(word(&data, 12) ^ xor, entry.offset);
+    assert_eq!(word(&data, 16) ^ xor, entry.size);
+    assert_eq!(word(&data, 20) ^ xor, entry.magic);
+    assert_eq!(word(&data, 24) ^ xor, entry.name.len() as u32);
+}
+
+/// A source of zeros that claims to be `len` bytes long without holding them.
+struct Sparse {
+    pos: u64,
+    len: u64,
+}
+
+impl Read for Sparse {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.len.saturating_sub(self.pos) as usize);
+        buf[..n].fill(0);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Sparse {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset).unwrap(),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset).unwrap(),
+        };
+        Ok(self.pos)
+    }
+}
+
+#[test]
+fn append_round_trips() {
+    for version in 1..=2 {
+        let (_, data) = sample(version);
+        let mut file = Cursor::new(data);
+        let mut archive = RGSSArchive::open(&mut file).unwrap();
+        archive
+            .append(&mut file, "Audio\\SE/new.ogg", Cursor::new(b"appended"))
+            .unwrap();
+        archive
+            .append(&mut file, "second", Cursor::new(b"x"))
+            .unwrap();
+        let e = archive
+            .append(&mut file, "second", Cursor::new(b"x"))
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        let data = file.into_inner();
+        let read = RGSSArchive::from_bytes(&data).unwrap();
+        assert_eq!(read, archive);
+        assert_eq!(read.len(), 5);
+        assert_eq!(read.entries[3].name, "Audio/SE/new.ogg");
+        assert_eq!(read.entries[3].extract_to_vec(&data).unwrap(), b"appended");
+        assert_eq!(read.entries[4].extract_to_vec(&data).unwrap(), b"x");
+        for (name, body) in common::FILES {
+            assert_eq!(
+                read.find(name).unwrap().extract_to_vec(&data).unwrap(),
+                *body
+            );
+        }
+    }
+}
+
+#[test]
+fn append_past_4_gib_leaves_the_archive_unchanged() {
+    let (_, data) = sample(1);
+    let mut archive = RGSSArchive::from_bytes(&data).unwrap();
+    let mut file = Cursor::new(data.clone());
+    // Too large for the size field, and too large to fit after the
+    // existing entries.
+    for len in [5 << 30, u32::MAX as u64 - 20] {
+        let e = archive
+            .append(&mut file, "huge.bin", Sparse { pos: 0, len })
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert!(e.to_string().contains("4 GiB"));
+        assert_eq!(file.get_ref(), &data);
+        assert_eq!(archive.len(), 3);
+    }
+}
+
+/// Counts the calls made to `write`.
+#[derive(Debug, Default)]
+struct WriteCounter {
+    data: Vec<u8>,
+    writes: usize,
+}
+
+impl Write for WriteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes += 1;
+        self.data.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn buffered_packing_batches_small_writes() {
+    let mut archive = RGSSArchive {
+        version: 1,
+        ..RGSSArchive::default()
+    };
+    for i in 0..1000 {
+        archive.entries.push(RGSSArchiveEntry {
+            name: format!("{}", i),
+            size: 3,
+            ..RGSSArchiveEntry::default()
+        });
+    }
+    let open = |_: &RGSSArchiveEntry| Ok(&b"abc"[..]);
+    let mut unbuffered = WriteCounter::default();
+    archive
+        .write_to(&mut [0; 8192], &mut unbuffered, open)
+        .unwrap();
+    // Every record is written field by field, followed by its body.
+    assert!(unbuffered.writes >= 4000);
+
+    // `pack` and `repack` wrap the output the same way.
+    let mut buffered = BufWriter::new(WriteCounter::default());
+    archive
+        .write_to(&mut [0; 8192], &mut buffered, open)
+        .unwrap();
+    let buffered = buffered.into_inner().unwrap();
+    assert_eq!(buffered.data, unbuffered.data);
+    assert!(buffered.writes <= buffered.data.len() / 8192 + 1);
+}
+
+#[test]
+fn open_sequential_reads_v3_without_seeking() {
+    let (archive, data) = sample(3);
+    // `&[u8]` implements `Read` but not `Seek`.
+    let mut r = &data[..];
+    let read = RGSSArchive::open_sequential(&mut r).unwrap();
+    assert_eq!(read, archive);
+    // Only the table was consumed; the bodies are still ahead.
+    assert_eq!(
+        r.len() as u64,
+        data.len() as u64 - archive.table_size().unwrap()
+    );
+
+    for version in 1..=2 {
+        let (archive, data) = sample(version);
+        assert_eq!(
+            RGSSArchive::open_sequential(&mut &data[..]).unwrap(),
+            archive
+        );
+    }
+}
+
+#[test]
+fn total_size_does_not_wrap() {
+    let mut archive = RGSSArchive::default();
+    for (name, size) in [("a", u32::MAX), ("b", u32::MAX), ("c", 2)] {
+        archive.entries.push(RGSSArchiveEntry {
+            name: name.to_owned(),
+            size,
+            ..RGSSArchiveEntry::default()
+        });
+    }
+    assert_eq!(archive.total_size(), 2 * u32::MAX as u64 + 2);
+    assert_eq!(archive.total_size(), 1 << 33);
+}
+
+#[test]
+fn invalid_names_report_their_record() {
+    for version in 1..=3 {
+        let (archive, mut data) = sample(version);
+        let first = &archive.entries[0];
+        let (record, name_at) = match version {
+            3 => {
+                let record = 12 + 16 + first.name.len() as u64;
+                (record, record + 16)
+            }
+            _ => {
+                let record = first.offset as u64 + first.size as u64;
+                (record, record + 4)
+            }
+        };
+        // Turns the stored "G" of the second name into an invalid byte.
+        data[name_at as usize] ^= b'G' ^ 0xff;
+        match RGSSArchive::from_bytes(&data) {
+            Err(RgssError::InvalidName {
+                encoding: NameEncoding::Utf8,
+                offset,
+            }) => assert_eq!(offset, Some(record), "v{}", version),
+            result => panic!("v{}: {:?}", version, result),
+        }
+    }
+}
+
+#[test]
+fn computed_size_matches_the_written_file() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        let mut fresh = archive.metadata_only();
+        assert_eq!(fresh.computed_size().unwrap(), data.len() as u64);
+        // Offsets and keys are assigned as writing assigns them.
+        assert_eq!(fresh, archive);
+
+        let mut empty = RGSSArchive {
+            version,
+            ..RGSSArchive::default()
+        };
+        let len = empty.computed_size().unwrap();
+        assert_eq!(
+            len,
+            empty.write_to_vec(|_| Vec::new()).unwrap().len() as u64
+        );
+    }
+}
+
+#[test]
+fn computed_size_is_arithmetic() {
+    // Three bodies of 1 GiB each, which would take seconds to stream.
+    for version in 1..=3 {
+        let mut archive = RGSSArchive {
+            version,
+            ..RGSSArchive::default()
+        };
+        for name in ["a.bin", "bb.bin", "ccc.bin"] {
+            archive.entries.push(RGSSArchiveEntry {
+                name: name.to_owned(),
+                size: 1 << 30,
+                ..RGSSArchiveEntry::default()
+            });
+        }
+        let names = 5 + 6 + 7;
+        let expected = match version {
+            3 => 16 + 3 * 16 + names + (3 << 30),
+            _ => 8 + 3 * 8 + names + (3 << 30),
+        };
+        assert_eq!(archive.computed_size().unwrap(), expected);
+        assert_eq!(archive.data_end(), expected);
+    }
+}
+
+#[test]
+fn body_ranges_are_in_bounds_and_disjoint() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        let ranges: Vec<_> = archive.iter_with_offsets().collect();
+        assert_eq!(ranges.len(), archive.len());
+        let table_end = match version {
+            3 => archive.table_size().unwrap(),
+            _ => 8,
+        };
+        let mut last_end = table_end;
+        for ((name, range), entry) in ranges.iter().zip(&archive.entries) {
+            assert_eq!(*name, entry.name);
+            assert_eq!(range.end - range.start, entry.size as u64);
+            assert!(range.start >= last_end && range.end <= data.len() as u64);
+            last_end = range.end;
+            // The range holds the encrypted body.
+            let mut body = data[range.start as usize..range.end as usize].to_vec();
+            Keystream::new(entry.magic).apply(&mut body);
+            assert_eq!(body, common::body(name));
+        }
+    }
+}
+
+#[test]
+fn reserved_header_byte_must_be_zero() {
+    let mut data = sample(1).1;
+    data[6] = 1;
+    match RGSSArchive::from_bytes(&data) {
+        Err(e @ RgssError::InvalidHeader(found)) => {
+            assert_eq!(found, *b"RGSSAD\x01\x01");
+            assert!(e.to_string().ends_with("(mismatch at byte 6)"), "{}", e);
+        }
+        result => panic!("{:?}", result),
+    }
+    // The version byte is checked separately.
+    data[6] = 0;
+    assert!(RGSSArchive::detect_version(&mut &data[..]).is_ok());
+}
+
+#[test]
+fn read_from_resumes_a_partial_read() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        let entry = archive.find("Data/Map001.rxdata").unwrap();
+        let mut out = Vec::new();
+        entry
+            .extract_range(&mut [0; 8], &mut Cursor::new(&data), &mut out, 0, 8)
+            .unwrap();
+        entry
+            .read_from(&mut [0; 8], &mut Cursor::new(&data), &mut out, 8)
+            .unwrap();
+        assert_eq!(out, body(&entry.name));
+        // Starting past the end of the body is an error.
+        entry
+            .read_from(&mut [0; 8], &mut Cursor::new(&data), &mut out, 24)
+            .unwrap_err();
+        let mut rest = Vec::new();
+        entry
+            .read_from(&mut [0; 8], &mut Cursor::new(&data), &mut rest, 20)
+            .unwrap();
+        assert_eq!(rest, &body(&entry.name)[20..]);
+    }
+}
+
+#[test]
+fn sort_entries_orders() {
+    let mut archive = RGSSArchive::default();
+    for (name, size) in [("b", 1), ("C", 3), ("a/x", 2), ("a\\w", 3), ("A", 2)] {
+        archive.entries.push(RGSSArchiveEntry {
+            name: name.to_owned(),
+            size,
+            ..RGSSArchiveEntry::default()
+        });
+    }
+    let names = |archive: &RGSSArchive| -> Vec<String> {
+        archive
+            .entries
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect()
+    };
+    let sorted = |order| {
+        let mut archive = archive.clone();
+        archive.sort_entries(order);
+        names(&archive)
+    };
+    assert_eq!(sorted(EntryOrder::AsInserted), names(&archive));
+    // `\` compares as `/`, so "a\w" comes before "a/x".
+    assert_eq!(sorted(EntryOrder::ByName), ["A", "C", "a\\w", "a/x", "b"]);
+    assert_eq!(
+        sorted(EntryOrder::ByNameCaseInsensitive),
+        ["A", "a/x", "a\\w", "b", "C"]
+    );
+    // Sorting is stable, so entries of the same size keep their order.
+    assert_eq!(sorted(EntryOrder::BySize), ["b", "a/x", "A", "C", "a\\w"]);
+}
+
+#[test]
+fn invalid_header_message_shows_what_was_found() {
+    let zip = b"PK\x03\x04\x14\x00\x00\x00\x08\x00";
+    let e = RGSSArchive::from_bytes(zip).unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "Invalid header: expected \"RGSSAD\\0\", found \"PK\\x03\\x04\\x14\\x00\\x00\\x00\" (mismatch at byte 0)"
+    );
+}
+
+#[test]
+fn rename_and_remove_entries() {
+    let (mut archive, _) = sample(1);
+    assert!(archive.rename_entry("Data\\Map001.rxdata", "Data/Map002.rxdata"));
+    assert!(archive.find("Data/Map001.rxdata").is_none());
+    assert!(archive.find("Data/Map002.rxdata").is_some());
+    // The target is taken, or the source is missing.
+    assert!(!archive.rename_entry("empty.txt", "Data/Map002.rxdata"));
+    assert!(!archive.rename_entry("missing", "other"));
+    assert!(archive.rename_entry("empty.txt", "empty.txt"));
+
+    assert!(archive.remove_entry("Graphics\\Titles\\title.png"));
+    assert!(!archive.remove_entry("Graphics/Titles/title.png"));
+    let names: Vec<_> = archive.entries.iter().map(|entry| &*entry.name).collect();
+    assert_eq!(names, ["Data/Map002.rxdata", "empty.txt"]);
+
+    // The edited table writes out as a valid archive.
+    let data = archive.write_to_vec(|_| b"body".to_vec()).unwrap();
+    assert_eq!(RGSSArchive::from_bytes(&data).unwrap(), archive);
+}
+
+#[test]
+fn write_to_vec_round_trips() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        // write_to_vec matches a streamed write byte for byte.
+        let mut streamed = Vec::new();
+        archive
+            .metadata_only()
+            .write_to(&mut [0; 8], &mut streamed, |entry| Ok(body(&entry.name)))
+            .unwrap();
+        assert_eq!(streamed, data);
+
+        let read = RGSSArchive::from_bytes(&data).unwrap();
+        assert_eq!(read, archive);
+        for (name, body) in common::FILES {
+            assert_eq!(
+                read.find(name).unwrap().extract_to_vec(&data).unwrap(),
+                *body
+            );
+        }
+    }
+}
+
+#[test]
+fn max_entries_bounds_the_table() {
+    for version in 1..=3 {
+        let (_, data) = sample(version);
+        let options = |max_entries| ReadOptions {
+            max_entries,
+            ..ReadOptions::default()
+        };
+        assert!(matches!(
+            RGSSArchive::open_with(&mut Cursor::new(&data), &options(2)),
+            Err(RgssError::TooManyEntries(2))
+        ));
+        let archive = RGSSArchive::open_with(&mut Cursor::new(&data), &options(3)).unwrap();
+        assert_eq!(archive.len(), 3);
+
+        // Reading without seeking is bounded the same way.
+        assert!(matches!(
+            RGSSArchive::open_sequential_with(&mut &data[..], &options(2)),
+            Err(RgssError::TooManyEntries(2))
+        ));
+        let archive = RGSSArchive::open_sequential_with(&mut &data[..], &options(3)).unwrap();
+        assert_eq!(archive.len(), 3);
+        if version == 3 {
+            continue;
+        }
+        let mut stream = RGSSArchive::stream_entries_with(&data[..], options(2)).unwrap();
+        for _ in 0..2 {
+            stream.next_entry().unwrap().unwrap();
+        }
+        assert!(matches!(
+            stream.next_entry(),
+            Err(RgssError::TooManyEntries(2))
+        ));
+    }
+}
+
+#[test]
+fn offsets_past_4_gib_fail_without_panicking() {
+    for version in 1..=3 {
+        let mut archive = RGSSArchive {
+            version,
+            ..RGSSArchive::default()
+        };
+        for name in ["a", "b", "c"] {
+            archive.entries.push(RGSSArchiveEntry {
+                name: name.to_owned(),
+                size: u32::MAX / 2,
+                ..RGSSArchiveEntry::default()
+            });
+        }
+        // The layout is checked before anything is written.
+        let mut out = Vec::new();
+        let e = archive.write_entries(&mut out).unwrap_err();
+        let e = e.into_inner().unwrap().downcast::<RgssError>().unwrap();
+        assert!(matches!(*e, RgssError::OffsetOverflow(ref name) if name == "b"));
+        assert!(e.to_string().contains("4 GiB"));
+        assert!(out.is_empty());
+        let e = archive
+            .write_to_with(&mut out, |_, _| unreachable!())
+            .unwrap_err();
+        assert!(e.to_string().contains("4 GiB"));
+        assert!(out.is_empty());
+        assert!(archive.computed_size().is_err());
+    }
+}
+
+/// A file that shrank after its length was taken: seeking from the end uses
+/// `len`, but reads stop at the end of `data`.
+struct Shrunk<'a> {
+    data: Cursor<&'a [u8]>,
+    len: u64,
+}
+
+impl Read for Shrunk<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+impl Seek for Shrunk<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::End(offset) => {
+                let pos = self.len.checked_add_signed(offset).unwrap();
+                self.data.seek(SeekFrom::Start(pos))
+            }
+            pos => self.data.seek(pos),
+        }
+    }
+}
+
+#[test]
+fn read_to_writer_reports_short_reads() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        let entry = archive.find("Data/Map001.rxdata").unwrap();
+        let mut out = Vec::new();
+        let written = entry
+            .read_to_writer(&mut [0; 8], &mut Cursor::new(&data), &mut out)
+            .unwrap();
+        assert_eq!(written, entry.size as u64);
+        assert_eq!(out, body(&entry.name));
+
+        let mut shrunk = Shrunk {
+            data: Cursor::new(&data[..entry.offset as usize + 3]),
+            len: data.len() as u64,
+        };
+        match entry.read_to_writer(&mut [0; 8], &mut shrunk, &mut Vec::new()) {
+            Err(RgssError::Io(e)) => {
+                assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+                assert_eq!(e.to_string(), "Entry body ended early: 3 of 22 bytes");
+            }
+            result => panic!("{:?}", result),
+        }
+        // A body that is known to be cut off fails before reading.
+        assert!(matches!(
+            entry.read_to_writer(
+                &mut [0; 8],
+                &mut Cursor::new(&data[..entry.offset as usize + 3]),
+                &mut Vec::new()
+            ),
+            Err(RgssError::TruncatedEntry)
+        ));
+    }
+}
+
+#[test]
+fn codec_buffer_rounds_up_to_whole_words() {
+    for (size, len) in [(0, 4), (1, 4), (4, 4), (5, 8), (8191, 8192), (8192, 8192)] {
+        assert_eq!(codec_buffer(size).len(), len, "{}", size);
+    }
+    // Any buffer it returns decrypts the same as any other.
+    let (archive, data) = sample(1);
+    let entry = &archive.entries[0];
+    for size in [0, 1, 5, 9] {
+        let mut out = Vec::new();
+        entry
+            .read(&mut codec_buffer(size), &mut Cursor::new(&data), &mut out)
+            .unwrap();
+        assert_eq!(out, body(&entry.name));
+    }
+}
+
+/// A xorshift generator, so that the garbage tests are reproducible.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+/// Runs every parser over `data`, extracting whatever they accept. Only a
+/// panic fails the test.
+fn parse_everything(data: &[u8]) {
+    if let Ok(archive) = RGSSArchive::from_bytes(data) {
+        for entry in &archive.entries {
+            let _ = entry.extract_to_vec(data);
+            let _ = entry.read(&mut [0; 8], &mut Cursor::new(data), &mut io::sink());
+        }
+    }
+    let _ = RGSSArchive::open_sequential(&mut &data[..]);
+    let options = ReadOptions {
+        allow_trailing_data: true,
+        ..ReadOptions::default()
+    };
+    let _ = RGSSArchive::open_with(&mut Cursor::new(data), &options);
+    let mut archive = RGSSArchive::default();
+    let mut r = Cursor::new(data);
+    if archive.read_header(&mut r).is_ok() {
+        if let Ok(skipped) = archive.read_entries_lenient(&mut r, &ReadOptions::default()) {
+            for range in skipped {
+                assert!(range.start <= range.end && range.end <= data.len() as u64);
+            }
+            for entry in &archive.entries {
+                let _ = entry.extract_to_vec(data);
+            }
+        }
+    }
+}
+
+#[test]
+fn garbage_never_panics() {
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    for version in 1..=3 {
+        let (_, data) = sample(version);
+        for len in 0..data.len() {
+            parse_everything(&data[..len]);
+        }
+        for _ in 0..200 {
+            let mut mutated = data.clone();
+            for _ in 0..1 + rng.next() % 4 {
+                let i = 8 + rng.next() as usize % (mutated.len() - 8);
+                mutated[i] = rng.next() as u8;
+            }
+            parse_everything(&mutated);
+
+            let len = rng.next() as usize % 256;
+            let mut random = b"RGSSAD\0".to_vec();
+            random.push(version);
+            random.extend(rng.bytes(len));
+            parse_everything(&random);
+        }
+    }
+    for _ in 0..100 {
+        let len = rng.next() as usize % 64;
+        parse_everything(&rng.bytes(len));
+    }
+}
+
+#[test]
+fn changing_the_version_re_encrypts_correctly() {
+    // v1 keys carry over into the v3 table, and v3 keys are replaced by the
+    // ones the v1 table derives; either way every body is encrypted again
+    // with the key written for it.
+    for (from, to) in [(1, 3), (3, 1), (2, 3), (3, 2)] {
+        let (_, data) = sample(from);
+        let mut archive = RGSSArchive::from_bytes(&data).unwrap();
+        let bodies: Vec<_> = archive
+            .entries
+            .iter()
+            .map(|entry| entry.extract_to_vec(&data).unwrap())
+            .collect();
+        archive.version = to;
+        let mut bodies = bodies.into_iter();
+        let converted = archive.write_to_vec(|_| bodies.next().unwrap()).unwrap();
+
+        let read = RGSSArchive::from_bytes(&converted).unwrap();
+        assert_eq!(read.version, to);
+        for (name, body) in common::FILES {
+            let entry = read.find(name).unwrap();
+            assert_eq!(
+                entry.extract_to_vec(&converted).unwrap(),
+                *body,
+                "v{} to v{}",
+                from,
+                to
+            );
+        }
+    }
+}
+
+#[test]
+fn versions_for_extensions() {
+    for (extension, version) in [
+        ("rgssad", Some(1)),
+        ("rgss2a", Some(2)),
+        ("rgss3a", Some(3)),
+        ("RGSS2A", Some(2)),
+        ("zip", None),
+        ("", None),
+    ] {
+        assert_eq!(
+            RGSSArchive::version_for_extension(extension),
+            version,
+            "{}",
+            extension
+        );
+    }
+    for version in 1..=3 {
+        let extension = RGSSArchive::default_extension(version).unwrap();
+        assert_eq!(RGSSArchive::version_for_extension(extension), Some(version));
+    }
+    assert_eq!(RGSSArchive::default_extension(4), None);
+}
+
+#[test]
+fn write_to_keeping_offsets_moves_only_changed_bodies() {
+    let write = |archive: &mut RGSSArchive, bodies: &dyn Fn(&str) -> Vec<u8>| {
+        let mut data = Vec::new();
+        archive
+            .write_to_keeping_offsets(&mut data, |entry, w| {
+                entry.write_sequential(&mut codec_buffer(64), w, &mut &bodies(&entry.name)[..])
+            })
+            .unwrap();
+        data
+    };
+    let (mut archive, _) = sample(3);
+    let table_end = archive.table_size().unwrap() as u32;
+    // Bodies out of table order, with gaps before and between them.
+    archive.entries[0].offset = table_end + 100;
+    archive.entries[1].offset = table_end + 7;
+    archive.entries[2].offset = table_end + 40;
+    let data = write(&mut archive, &|name| body(name).to_vec());
+    let template = RGSSArchive::from_bytes(&data).unwrap();
+    assert_eq!(template, archive);
+    assert_eq!(data.len() as u64, template.data_end());
+    for entry in &template {
+        assert_eq!(entry.extract_to_vec(&data).unwrap(), body(&entry.name));
+    }
+
+    let changed = "Graphics/Titles/title.png";
+    let changed_body = [body(changed), b" v2"].concat();
+    let mut archive = template.clone();
+    archive.entries[1].size = changed_body.len() as u32;
+    archive.entries[1].offset = 0;
+    let data = write(&mut archive, &|name| match name == changed {
+        true => changed_body.clone(),
+        false => body(name).to_vec(),
+    });
+    let read = RGSSArchive::from_bytes(&data).unwrap();
+    assert_eq!(read.entries[0].offset, template.entries[0].offset);
+    assert_eq!(read.entries[2].offset, template.entries[2].offset);
+    assert_eq!(read.entries[1].offset, template.data_end() as u32);
+    assert_eq!(read.entries[1].extract_to_vec(&data).unwrap(), changed_body);
+    assert_eq!(
+        read.entries[0].extract_to_vec(&data).unwrap(),
+        body(&read.entries[0].name)
+    );
+}
+
+#[test]
+fn write_to_keeping_offsets_matches_write_to_for_v1() {
+    let (mut archive, data) = sample(1);
+    let mut kept = Vec::new();
+    archive
+        .write_to_keeping_offsets(&mut kept, |entry, w| {
+            entry.write_sequential(&mut codec_buffer(64), w, &mut body(&entry.name))
+        })
+        .unwrap();
+    assert_eq!(kept, data);
+}
+
+#[test]
+fn entry_display_matches_list_format() {
+    let entry = RGSSArchiveEntry {
+        name: "Data/Map001.rxdata".to_owned(),
+        size: 22,
+        offset: 48,
+        magic: 0xdeadcafe,
+    };
+    assert_eq!(
+        entry.to_string(),
+        "Data/Map001.rxdata: { size: 22, offset: 48, magic: 3735931646 }"
+    );
+}
+
+#[test]
+fn version_4_is_reported_by_number() {
+    let data = b"RGSSAD\x00\x04\x00\x00\x00\x00";
+    let e = RGSSArchive::from_bytes(data).unwrap_err();
+    assert!(matches!(e, RgssError::UnsupportedVersion(4)), "{:?}", e);
+    assert_eq!(e.to_string(), "Unsupported version: 4 (expected 1, 2 or 3)");
+
+    let mut archive = RGSSArchive {
+        version: 4,
+        ..RGSSArchive::default()
+    };
+    let e = archive
+        .read_entries(&mut Cursor::new(&data[8..]))
+        .unwrap_err();
+    assert!(matches!(e, RgssError::UnsupportedVersion(4)), "{:?}", e);
+}
+
+#[test]
+fn build_index_agrees_with_find() {
+    let mut archive = RGSSArchive::default();
+    for i in 0..1000 {
+        archive.entries.push(RGSSArchiveEntry {
+            name: format!("Data/Map{:03}.rxdata", i % 700),
+            size: i,
+            ..RGSSArchiveEntry::default()
+        });
+    }
+    let index = archive.build_index();
+    assert_eq!(index.len(), 700);
+    for entry in &archive {
+        let found = archive.find(&entry.name).unwrap();
+        assert!(std::ptr::eq(&archive.entries()[index[&*entry.name]], found));
+        // Duplicates resolve to the first entry, as a linear scan does.
+        assert_eq!(found.size, entry.size % 700);
+    }
+    assert!(!index.contains_key("Data\\Map000.rxdata"));
+    assert_eq!(archive.find("Data/Map700.rxdata"), None);
+}
+
+#[test]
+fn bodies_past_the_end_are_truncated() {
+    let (archive, data) = sample(3);
+    let len = data.len() as u32;
+    let entry = &archive.entries()[0];
+    for (offset, size) in [(len + 100, entry.size), (len - 4, entry.size), (len, 1)] {
+        let entry = RGSSArchiveEntry {
+            offset,
+            size,
+            ..entry.clone()
+        };
+        let mut buf = codec_buffer(64);
+        let mut r = Cursor::new(&data);
+        let mut out = Vec::new();
+        for result in [
+            entry.read(&mut buf, &mut r, &mut out),
+            entry.read_from(&mut buf, &mut r, &mut out, 0),
+            entry.extract_range(&mut buf, &mut r, &mut out, 0, 1),
+            entry.reader(&mut r).map(drop),
+            entry.extract_to_vec(&data).map(drop),
+        ] {
+            assert!(
+                matches!(result, Err(RgssError::TruncatedEntry)),
+                "{:?} at {}",
+                result,
+                offset
+            );
+        }
+        assert!(out.is_empty());
+    }
+}
+
+#[test]
+fn trailing_padding_ends_v1_tables() {
+    let (archive, data) = sample(1);
+    // Encrypts a record with the keys that follow the last real one.
+    let record = |name: &[u8], size: u32| {
+        let used = archive
+            .entries()
+            .iter()
+            .map(|entry| entry.name.len() + 2)
+            .sum();
+        let mut keys = rgssad_name_keystream(RGSSAD_INITIAL_MAGIC).skip(used);
+        let mut record = (name.len() as u32 ^ keys.next().unwrap())
+            .to_le_bytes()
+            .to_vec();
+        for byte in name {
+            record.push(byte ^ keys.next().unwrap() as u8);
+        }
+        record.extend((size ^ keys.next().unwrap()).to_le_bytes());
+        record
+    };
+    let options = ReadOptions {
+        allow_trailing_data: true,
+        ..ReadOptions::default()
+    };
+    for padding in [
+        vec![0; 64],
+        vec![0xff; 3],
+        record(b"\x01", 0),
+        record(b"junk", 1000),
+    ] {
+        let padded = [&data[..], &padding].concat();
+        let read = RGSSArchive::open_with(&mut Cursor::new(&padded), &options).unwrap();
+        assert_eq!(read.entries(), archive.entries(), "{:?}", padding);
+        assert_ne!(
+            RGSSArchive::from_bytes(&padded).ok().as_ref(),
+            Some(&archive)
+        );
+    }
+}
+
+#[test]
+fn push_entry_normalizes_and_rejects_duplicates() {
+    let entry = |name: &str| RGSSArchiveEntry {
+        name: name.to_owned(),
+        size: 4,
+        ..RGSSArchiveEntry::default()
+    };
+    let mut archive = RGSSArchive::default();
+    archive.push_entry(entry("Data\\Map001.rxdata")).unwrap();
+    assert_eq!(archive.entries()[0].name, "Data/Map001.rxdata");
+    archive.push_entry(entry("Data/Map002.rxdata")).unwrap();
+
+    for name in ["Data/Map001.rxdata", "Data\\Map002.rxdata"] {
+        let e = archive.push_entry(entry(name)).unwrap_err();
+        assert!(
+            matches!(&e, RgssError::DuplicateNames(names) if *names == [name.replace('\\', "/")]),
+            "{:?}",
+            e
+        );
+    }
+    assert_eq!(archive.len(), 2);
+}
+
+#[test]
+fn v2_archives_are_written_back_as_v2() {
+    let (_, v1) = sample(1);
+    let (_, v2) = sample(2);
+    assert_eq!(&v2[..8], b"RGSSAD\x00\x02");
+    // The two versions differ only in the header byte.
+    assert_eq!(v1[8..], v2[8..]);
+
+    let mut archive = RGSSArchive::from_bytes(&v2).unwrap();
+    assert_eq!(archive.version, 2);
+    let mut header = Vec::new();
+    archive.write_header(&mut header).unwrap();
+    assert_eq!(header, v2[..8]);
+    let mut rewritten = Vec::new();
+    archive
+        .write_to_with(&mut rewritten, |entry, w| {
+            Ok(entry.copy_raw(&mut Cursor::new(&v2), w)?)
+        })
+        .unwrap();
+    assert_eq!(rewritten, v2);
+}
+
+#[test]
+fn metadata_only_clears_offsets_and_keys() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        let mut copy = archive.metadata_only();
+        assert_eq!(copy.version, archive.version);
+        assert_eq!(copy.magic, archive.magic);
+        assert_eq!(copy.len(), archive.len());
+        for (copied, entry) in copy.entries().iter().zip(&archive) {
+            assert_eq!((&copied.name, copied.size), (&entry.name, entry.size));
+            assert_eq!((copied.offset, copied.magic), (0, 0));
+        }
+        // Laid out afresh, the copy comes out as the archive was first written.
+        assert_eq!(copy.write_to_vec(|name| body(name).to_vec()).unwrap(), data);
+        assert_eq!(copy, archive);
+    }
+}
+
+#[test]
+fn sources_of_the_wrong_length_are_rejected() {
+    let entry = RGSSArchiveEntry {
+        name: "Data/Map001.rxdata".to_owned(),
+        size: 8,
+        magic: RGSSAD_INITIAL_MAGIC,
+        ..RGSSArchiveEntry::default()
+    };
+    let mut buf = codec_buffer(4);
+    for source in [&b"short"[..], b"one byte over"] {
+        let e = entry
+            .write_sequential(&mut buf, &mut io::sink(), &mut &source[..])
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            e.to_string(),
+            "Source length does not match the entry size: Data/Map001.rxdata"
+        );
+    }
+    entry
+        .write_sequential(&mut buf, &mut io::sink(), &mut &b"8 bytes!"[..])
+        .unwrap();
+
+    // A file that changed after its size was taken fails the whole write.
+    let (mut archive, _) = sample(1);
+    let e = archive
+        .write_to(&mut codec_buffer(64), &mut io::sink(), |entry| {
+            Ok(Cursor::new([body(&entry.name), b"!"].concat()))
+        })
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+}
+
+/// Counts what is written to it, failing any single write larger than
+/// `limit`.
+struct ChunkLimit {
+    limit: usize,
+    written: u64,
+}
+
+impl Write for ChunkLimit {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        assert!(buf.len() <= self.limit, "wrote {} bytes at once", buf.len());
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn reading_streams_in_buffer_sized_chunks() {
+    const SIZE: u32 = (4 << 20) + 3;
+    let entry = RGSSArchiveEntry {
+        name: "large.bin".to_owned(),
+        size: SIZE,
+        offset: 16,
+        magic: RGSSAD_INITIAL_MAGIC,
+    };
+    let mut buf = codec_buffer(4096);
+    let mut r = Sparse {
+        pos: 0,
+        len: 16 + SIZE as u64,
+    };
+    let mut w = ChunkLimit {
+        limit: buf.len(),
+        written: 0,
+    };
+    let mut reports = Vec::new();
+    entry
+        .read_with_progress(&mut buf, &mut r, &mut w, |name, done, total| {
+            assert_eq!((name, total), ("large.bin", SIZE as u64));
+            reports.push(done);
+        })
+        .unwrap();
+    assert_eq!(w.written, SIZE as u64);
+    assert_eq!(reports.len(), SIZE as usize / 4096 + 2);
+    assert_eq!(reports[0], 0);
+    assert_eq!(*reports.last().unwrap(), SIZE as u64);
+    for pair in reports.windows(2) {
+        assert!(pair[1] > pair[0] && pair[1] - pair[0] <= 4096, "{:?}", pair);
+    }
+}
+
+#[test]
+fn cmp_by_name_sorts_by_name_then_offset() {
+    let entry = |name: &str, offset| RGSSArchiveEntry {
+        name: name.to_owned(),
+        offset,
+        ..RGSSArchiveEntry::default()
+    };
+    let mut entries = [
+        entry("b", 0),
+        entry("a/y", 10),
+        entry("a\\x", 30),
+        entry("a/x", 20),
+        entry("B", 40),
+        entry("a", 50),
+    ];
+    entries.sort_by(RGSSArchiveEntry::cmp_by_name);
+    let order: Vec<_> = entries
+        .iter()
+        .map(|entry| (entry.name.as_str(), entry.offset))
+        .collect();
+    assert_eq!(
+        order,
+        [
+            ("B", 40),
+            ("a", 50),
+            ("a/x", 20),
+            ("a\\x", 30),
+            ("a/y", 10),
+            ("b", 0)
+        ]
+    );
+    assert_eq!(
+        entry("a/x", 5).cmp_by_name(&entry("a\\x", 5)),
+        std::cmp::Ordering::Equal
+    );
+}
+
+#[test]
+fn lenient_reading_skips_a_corrupt_middle_record() {
+    let files: &[(&str, &[u8])] = &[
+        ("Data/Map001.rxdata", b"first map body"),
+        ("Data/Map002.rxdata", b"second map body"),
+        ("Data/Map003.rxdata", b"third map body"),
+        ("Graphics/title.png", b"title image body"),
+    ];
+    let (archive, mut data) = common::build(1, files);
+    let entries = archive.entries();
+    // Garbles the name length of the second record.
+    let corrupt = (entries[0].offset + entries[0].size) as usize;
+    data[corrupt + 3] ^= 0x80;
+    assert!(RGSSArchive::from_bytes(&data).is_err());
+
+    let mut read = RGSSArchive::default();
+    let mut r = Cursor::new(&data);
+    read.read_header(&mut r).unwrap();
+    let skipped = read
+        .read_entries_lenient(&mut r, &ReadOptions::default())
+        .unwrap();
+    let third = entries[2].offset as u64 - 8 - entries[2].name.len() as u64;
+    assert_eq!(skipped, vec![corrupt as u64..third]);
+    assert_eq!(
+        read.entries(),
+        [&entries[0], &entries[2], &entries[3]].map(Clone::clone)
+    );
+    for (entry, (_, body)) in read.entries().iter().zip([files[0], files[2], files[3]]) {
+        assert_eq!(entry.extract_to_vec(&data).unwrap(), body);
+    }
+}
+
+#[test]
+fn table_size_is_where_v3_bodies_start() {
+    let names = ["Data/Map001.rxdata", "Graphics/タイトル.png", "x"];
+    // 16 bytes of header, key and terminator, then 16 per record plus the
+    // name, whose katakana take 3 bytes each in UTF-8 and 2 in Shift-JIS.
+    for (encoding, names, expected) in [
+        (NameEncoding::Utf8, &names[..1], 16 + 16 + 18),
+        (NameEncoding::Utf8, &names[..], 16 + 48 + 18 + 25 + 1),
+        (NameEncoding::ShiftJis, &names[..], 16 + 48 + 18 + 21 + 1),
+    ] {
+        let mut archive = RGSSArchive {
+            version: 3,
+            encoding,
+            ..RGSSArchive::default()
+        };
+        for name in names {
+            archive
+                .push_entry(RGSSArchiveEntry {
+                    name: name.to_string(),
+                    size: 3,
+                    ..RGSSArchiveEntry::default()
+                })
+                .unwrap();
+        }
+        let table_size = archive.table_size().unwrap();
+        assert_eq!(table_size, expected);
+        let data = archive.write_to_vec(|_| b"abc".to_vec()).unwrap();
+        assert_eq!(table_size, archive.entries()[0].offset as u64);
+        assert_eq!(table_size + archive.total_size(), data.len() as u64);
+    }
+    for version in 1..=2 {
+        let (archive, data) = sample(version);
+        assert_eq!(
+            archive.table_size().unwrap() + archive.total_size(),
+            data.len() as u64
+        );
+    }
+}
+
+#[test]
+fn sanitize_name_rejects_nul_and_mixes_separators() {
+    for name in ["Data/Map\0.rxdata", "\0", "Data\\\0\\Map001.rxdata"] {
+        assert!(
+            matches!(sanitize_name(name), Err(RgssError::UnsafeName(_))),
+            "{:?}",
+            name
+        );
+    }
+    assert_eq!(
+        sanitize_name("Graphics\\Titles/title.png").unwrap(),
+        Path::new("Graphics").join("Titles").join("title.png")
+    );
+}
+
+#[test]
+fn empty_archives_round_trip_in_every_version() {
+    for version in 1..=3 {
+        let mut archive = RGSSArchive {
+            version,
+            ..RGSSArchive::default()
+        }
+        .with_magic(0x1234_5678);
+        let data = archive.write_to_vec(|_| unreachable!()).unwrap();
+        let expected = match version {
+            3 => [
+                &b"RGSSAD\x00\x03"[..],
+                &0x1234_5678u32.to_le_bytes(),
+                &rgss3a_xor(0x1234_5678).to_le_bytes(),
+            ]
+            .concat(),
+            _ => [&b"RGSSAD\x00"[..], &[version]].concat(),
+        };
+        assert_eq!(data, expected, "v{}", version);
+        assert_eq!(archive.table_size().unwrap(), data.len() as u64);
+
+        let read = RGSSArchive::from_bytes(&data).unwrap();
+        assert!(read.is_empty());
+        assert_eq!(read.version, version);
+        assert_eq!(read.data_end(), data.len() as u64);
+        assert_eq!(read.verify(&mut Cursor::new(&data)).unwrap(), []);
+    }
+}
+
+#[test]
+fn v3_writes_to_sinks_without_seek() {
+    let (mut archive, data) = sample(3);
+    // `WriteCounter` implements `Write` but not `Seek`.
+    let mut sink = WriteCounter::default();
+    archive
+        .write_to(&mut codec_buffer(64), &mut sink, |entry| {
+            Ok(body(&entry.name))
+        })
+        .unwrap();
+    assert_eq!(sink.data, data);
+    let mut sink = WriteCounter::default();
+    archive
+        .write_to_with(&mut sink, |entry, w| {
+            entry.write_sequential(&mut codec_buffer(64), w, &mut body(&entry.name))
+        })
+        .unwrap();
+    assert_eq!(sink.data, data);
+}
+
+#[test]
+fn v1_entries_are_written_in_table_order() {
+    let (template, _) = sample(1);
+    for order in [[0, 1, 2], [2, 0, 1], [1, 2, 0]] {
+        let mut archive = RGSSArchive {
+            entries: order.map(|i| template.entries()[i].clone()).to_vec(),
+            ..template.clone()
+        };
+        let data = archive.write_to_vec(|name| body(name).to_vec()).unwrap();
+        let read = RGSSArchive::from_bytes(&data).unwrap();
+        let names: Vec<_> = read.entries().iter().map(|entry| &entry.name).collect();
+        let expected: Vec<_> = order.iter().map(|&i| &template.entries()[i].name).collect();
+        assert_eq!(names, expected);
+        for pair in read.entries().windows(2) {
+            assert!(pair[0].offset < pair[1].offset);
+        }
+        match order {
+            [0, 1, 2] => assert_eq!(archive.find_reordered(&template), None),
+            _ => assert!(archive.find_reordered(&template).is_some()),
+        }
+    }
+}
+
+#[test]
+fn unpack_with_renames_and_skips() {
+    let dir = TempDir::new();
+    let files: &[(&str, &[u8])] = &[
+        ("Data/Map001.rxdata", b"map"),
+        ("Data/Map001.rxdata.bak", b"old map"),
+        ("Graphics/Titles/Title.PNG", b"title"),
+    ];
+    let (archive, data) = common::build(1, files);
+    archive
+        .unpack_with(&mut Cursor::new(&data), dir.path(), |name| {
+            match name.ends_with(".bak") {
+                true => None,
+                false => sanitize_name(&name.to_lowercase()).ok(),
+            }
+        })
+        .unwrap();
+    assert_eq!(fs::read(dir.join("data/map001.rxdata")).unwrap(), b"map");
+    assert_eq!(
+        fs::read(dir.join("graphics/titles/title.png")).unwrap(),
+        b"title"
+    );
+    assert!(!dir.join("data/map001.rxdata.bak").exists());
+
+    let e = archive
+        .unpack_with(&mut Cursor::new(&data), dir.path(), |_| {
+            Some(Path::new("..").join("escape"))
+        })
+        .unwrap_err();
+    assert!(matches!(e, RgssError::UnsafeName(_)), "{:?}", e);
+}
+
+#[test]
+fn v1_tables_start_from_the_initial_magic() {
+    assert_eq!(RGSSAD_INITIAL_MAGIC, 0xdeadcafe);
+    let (archive, data) = sample(1);
+    // The first name length is encrypted with the initial key itself.
+    let first = u32::from_le_bytes(data[8..12].try_into().unwrap());
+    assert_eq!(
+        first ^ RGSSAD_INITIAL_MAGIC,
+        archive.entries()[0].name.len() as u32
+    );
+    let mut read = RGSSArchive::from_bytes(&data).unwrap();
+    assert_eq!(read, archive);
+    assert_eq!(read.write_to_vec(|name| body(name).to_vec()).unwrap(), data);
+}
+
+#[test]
+fn stream_entries_matches_extract_to_vec() {
+    for version in 1..=2 {
+        let (archive, data) = sample(version);
+        let mut stream = RGSSArchive::stream_entries(&data[..]).unwrap();
+        assert_eq!(stream.version(), version);
+        let mut streamed = Vec::new();
+        while let Some(mut entry) = stream.next_entry().unwrap() {
+            let mut body = Vec::new();
+            entry.read_to_end(&mut body).unwrap();
+            assert_eq!(body, entry.entry.extract_to_vec(&data).unwrap());
+            streamed.push(entry.entry);
+        }
+        assert_eq!(streamed, archive.entries());
+    }
+}
+
+#[test]
+fn stream_entries_skips_partly_read_bodies() {
+    let (archive, data) = sample(1);
+    // Reads nothing of the first body and 3 bytes of the second.
+    let mut stream = RGSSArchive::stream_entries(&data[..]).unwrap();
+    let mut names = Vec::new();
+    for read in [0, 3, 0] {
+        let mut entry = stream.next_entry().unwrap().unwrap();
+        let mut start = vec![0; read];
+        entry.read_exact(&mut start).unwrap();
+        assert_eq!(start, body(&entry.entry.name)[..read]);
+        names.push(entry.entry.name.clone());
+    }
+    assert!(stream.next_entry().unwrap().is_none());
+    let expected: Vec<_> = archive.entries().iter().map(|entry| &entry.name).collect();
+    assert_eq!(names.iter().collect::<Vec<_>>(), expected);
+
+    // A body cut off by the end of the stream cannot be skipped.
+    let cut = &data[..archive.entries()[0].offset as usize + 5];
+    let mut stream = RGSSArchive::stream_entries(cut).unwrap();
+    stream.next_entry().unwrap().unwrap();
+    assert!(matches!(
+        stream.next_entry(),
+        Err(RgssError::TruncatedEntry)
+    ));
+}
+
+#[test]
+fn write_with_progress_reports_up_to_the_size() {
+    let plain: Vec<u8> = (0..10_003u32).map(|i| i as u8).collect();
+    let entry = RGSSArchiveEntry {
+        name: "large.bin".to_owned(),
+        size: plain.len() as u32,
+        offset: 16,
+        magic: RGSSAD_INITIAL_MAGIC,
+    };
+    let mut w = Cursor::new(Vec::new());
+    let mut reports = Vec::new();
+    entry
+        .write_with_progress(
+            &mut codec_buffer(1024),
+            &mut w,
+            &mut &plain[..],
+            |name, done, total| {
+                assert_eq!((name, total), ("large.bin", plain.len() as u64));
+                reports.push(done);
+            },
+        )
+        .unwrap();
+    assert_eq!(reports.first(), Some(&0));
+    assert_eq!(reports.last(), Some(&(plain.len() as u64)));
+    for pair in reports.windows(2) {
+        assert!(pair[1] > pair[0] && pair[1] - pair[0] <= 1024, "{:?}", pair);
+    }
+    let data = w.into_inner();
+    assert_eq!(data.len(), 16 + plain.len());
+    assert_eq!(entry.extract_to_vec(&data).unwrap(), plain);
+}
+
+#[test]
+fn detect_version_reads_the_header_alone() {
+    for version in 1..=3 {
+        let (_, data) = sample(version);
+        let mut r = Cursor::new(&data);
+        assert_eq!(RGSSArchive::detect_version(&mut r).unwrap(), version);
+        // Left at the start of the table.
+        assert_eq!(r.position(), 8);
+    }
+    let e = RGSSArchive::detect_version(&mut &b"RGSSAD\x01\x01"[..]).unwrap_err();
+    assert!(
+        matches!(e, RgssError::InvalidHeader(found) if found == *b"RGSSAD\x01\x01"),
+        "{:?}",
+        e
+    );
+    let e = RGSSArchive::detect_version(&mut &b"RGSSAD\x00"[..]).unwrap_err();
+    assert!(matches!(e, RgssError::Io(_)), "{:?}", e);
+}
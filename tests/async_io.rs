@@ -0,0 +1,100 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use std::io::{self, Cursor};
+
+use common::sample;
+use rgssad::{RGSSArchiveEntry, RgssError};
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+#[test]
+fn read_async_matches_read() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        for entry in &archive.entries {
+            let mut sync = Vec::new();
+            entry
+                .read(&mut [0; 8], &mut Cursor::new(&data), &mut sync)
+                .unwrap();
+            let mut r#async = Vec::new();
+            block_on(entry.read_async(&mut [0; 8], &mut Cursor::new(&data), &mut r#async)).unwrap();
+            assert_eq!(r#async, sync);
+        }
+    }
+}
+
+#[test]
+fn read_async_checks_bounds_like_read() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        let entry = &archive.entries[1];
+        let cut = &data[..entry.offset as usize + 1];
+        let sync = entry.read(&mut [0; 8], &mut Cursor::new(cut), &mut Vec::new());
+        assert!(matches!(sync, Err(RgssError::TruncatedEntry)));
+        let r#async =
+            block_on(entry.read_async(&mut [0; 8], &mut Cursor::new(cut), &mut Vec::new()));
+        assert!(matches!(r#async, Err(RgssError::TruncatedEntry)));
+    }
+}
+
+#[test]
+fn write_async_matches_write() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        let mut sync = Cursor::new(Vec::new());
+        let mut r#async = Cursor::new(Vec::new());
+        for entry in &archive.entries {
+            let body = common::body(&entry.name);
+            entry.write(&mut [0; 8], &mut sync, &mut &body[..]).unwrap();
+            block_on(entry.write_async(&mut [0; 8], &mut r#async, &mut &body[..])).unwrap();
+        }
+        assert_eq!(r#async.get_ref(), sync.get_ref());
+        // Only the bodies were written, at their offsets in the archive.
+        for (_, range) in archive
+            .iter_with_offsets()
+            .filter(|(_, range)| !range.is_empty())
+        {
+            let range = range.start as usize..range.end as usize;
+            assert_eq!(sync.get_ref()[range.clone()], data[range]);
+        }
+    }
+}
+
+#[test]
+fn write_async_rejects_sources_of_the_wrong_length_like_write() {
+    let entry = RGSSArchiveEntry {
+        name: "Data/Map001.rxdata".to_owned(),
+        size: 8,
+        ..RGSSArchiveEntry::default()
+    };
+    for source in [&b"short"[..], b"one byte over"] {
+        let sync = entry
+            .write(&mut [0; 8], &mut Cursor::new(Vec::new()), &mut &source[..])
+            .unwrap_err();
+        let r#async = block_on(entry.write_async(
+            &mut [0; 8],
+            &mut Cursor::new(Vec::new()),
+            &mut &source[..],
+        ))
+        .unwrap_err();
+        assert_eq!(r#async.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(r#async.to_string(), sync.to_string());
+        assert_eq!(
+            r#async.to_string(),
+            "Source length does not match the entry size: Data/Map001.rxdata"
+        );
+    }
+    block_on(entry.write_async(
+        &mut [0; 8],
+        &mut Cursor::new(Vec::new()),
+        &mut &b"8 bytes!"[..],
+    ))
+    .unwrap();
+}
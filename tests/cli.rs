@@ -0,0 +1,1137 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use common::{sample, TempDir, FILES};
+use rgssad::{rgss3a_xor, RGSSArchive, RGSSArchiveEntry};
+
+fn rgssad() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_rgssad"))
+}
+
+#[test]
+fn unpack_refuses_escaping_names() {
+    let dir = TempDir::new();
+    let mut archive = RGSSArchive {
+        version: 1,
+        ..RGSSArchive::default()
+    };
+    archive.entries.push(RGSSArchiveEntry {
+        name: "../escape.txt".to_owned(),
+        ..RGSSArchiveEntry::default()
+    });
+    let path = dir.join("escape.rgssad");
+    std::fs::write(
+        &path,
+        archive.write_to_vec(|_| b"escaped".to_vec()).unwrap(),
+    )
+    .unwrap();
+
+    let output = rgssad()
+        .arg("unpack")
+        .arg(&path)
+        .arg(dir.join("out"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: Entry name is not a safe relative path: ../escape.txt\n"
+    );
+    assert!(!dir.join("escape.txt").exists());
+}
+
+#[test]
+fn pack_and_unpack_round_trip() {
+    for extension in ["rgssad", "rgss2a", "rgss3a"] {
+        let dir = TempDir::new();
+        let input = dir.write_files("in");
+        let path = dir.join(format!("Game.{}", extension));
+        let status = rgssad()
+            .arg("pack")
+            .arg(&input)
+            .arg(&path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let archive = RGSSArchive::from_bytes(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(
+            Some(archive.version),
+            RGSSArchive::version_for_extension(extension)
+        );
+
+        let status = rgssad()
+            .arg("unpack")
+            .arg(&path)
+            .arg(dir.join("out"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        for (name, body) in FILES {
+            assert_eq!(fs::read(dir.join("out").join(name)).unwrap(), *body);
+        }
+    }
+}
+
+#[test]
+fn repack_lists_every_missing_file() {
+    let dir = TempDir::new();
+    let template = dir.write_sample("Game.rgssad", 1);
+    let input = dir.write_files("in");
+    fs::remove_file(input.join("Data/Map001.rxdata")).unwrap();
+    fs::remove_file(input.join("empty.txt")).unwrap();
+
+    let output = rgssad()
+        .arg("repack")
+        .arg(&input)
+        .arg(dir.join("out.rgssad"))
+        .arg(&template)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Files missing from the directory: Data/Map001.rxdata, empty.txt"),
+        "{}",
+        stderr
+    );
+    assert!(!dir.join("out.rgssad").exists());
+}
+
+/// Lists the files under `dir` with `/` separators, or nothing if `dir` was
+/// never created.
+fn unpacked_files(dir: &Path) -> Vec<String> {
+    if !dir.exists() {
+        return Vec::new();
+    }
+    let mut files: Vec<_> = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .map(Result::unwrap)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| {
+            let path = entry.path().strip_prefix(dir).unwrap();
+            path.to_str().unwrap().replace('\\', "/")
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn unpack_glob_filter() {
+    let dir = TempDir::new();
+    let path = dir.write_sample("Game.rgssad", 1);
+    for (glob, expected) in [
+        ("Data/*.rxdata", &["Data/Map001.rxdata"][..]),
+        ("**/*.png", &["Graphics/Titles/title.png"][..]),
+        ("*.ogg", &[][..]),
+    ] {
+        let out = dir.join(glob.replace(['*', '/'], "_"));
+        let status = rgssad()
+            .args(["unpack", "--glob", glob])
+            .arg(&path)
+            .arg(&out)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(unpacked_files(&out), expected, "{}", glob);
+    }
+}
+
+#[test]
+fn dry_run_creates_no_files() {
+    let dir = TempDir::new();
+    let path = dir.write_sample("Game.rgssad", 1);
+    let output = rgssad()
+        .args(["unpack", "--dry-run"])
+        .arg(&path)
+        .arg(dir.join("out"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).lines().count(),
+        FILES.len()
+    );
+    assert!(!dir.join("out").exists());
+
+    let input = dir.write_files("in");
+    let output = rgssad()
+        .args(["pack", "--dry-run"])
+        .arg(&input)
+        .arg(dir.join("new.rgssad"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let listing = format!("Data/Map001.rxdata: {{ size: {},", FILES[0].1.len());
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&listing));
+    assert!(!dir.join("new.rgssad").exists());
+}
+
+#[test]
+fn unpack_overwrite() {
+    let dir = TempDir::new();
+    let path = dir.write_sample("Game.rgssad", 1);
+    let unpack = |overwrite: bool| {
+        let mut command = rgssad();
+        command.arg("unpack");
+        if overwrite {
+            command.arg("--overwrite");
+        }
+        command.arg(&path).arg(dir.join("out")).output().unwrap()
+    };
+    assert!(unpack(false).status.success());
+    fs::write(dir.join("out/empty.txt"), "changed").unwrap();
+
+    let output = unpack(false);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("use --overwrite"));
+    assert_eq!(fs::read(dir.join("out/empty.txt")).unwrap(), b"changed");
+
+    assert!(unpack(true).status.success());
+    for (name, body) in FILES {
+        assert_eq!(fs::read(dir.join("out").join(name)).unwrap(), *body);
+    }
+}
+
+#[test]
+fn unpack_flatten() {
+    let dir = TempDir::new();
+    let path = dir.write_archive(
+        "Game.rgssad",
+        1,
+        &[("a/x.txt", b"a"), ("b/x.txt", b"b"), ("c/y", b"c")],
+    );
+    let status = rgssad()
+        .args(["unpack", "--flatten"])
+        .arg(&path)
+        .arg(dir.join("out"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(unpacked_files(&dir.join("out")), ["x-2.txt", "x.txt", "y"]);
+    assert_eq!(fs::read(dir.join("out/x.txt")).unwrap(), b"a");
+    assert_eq!(fs::read(dir.join("out/x-2.txt")).unwrap(), b"b");
+    assert_eq!(fs::read(dir.join("out/y")).unwrap(), b"c");
+}
+
+#[test]
+fn unpack_strip_components() {
+    let dir = TempDir::new();
+    let path = dir.write_sample("Game.rgssad", 1);
+    let output = rgssad()
+        .args(["unpack", "--strip-components", "1"])
+        .arg(&path)
+        .arg(dir.join("one"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        unpacked_files(&dir.join("one")),
+        ["Map001.rxdata", "Titles/title.png"]
+    );
+    // empty.txt has a single component, so nothing is left after stripping it.
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Skipping empty.txt: not enough components"));
+
+    let output = rgssad()
+        .args(["unpack", "--strip-components", "3"])
+        .arg(&path)
+        .arg(dir.join("three"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(unpacked_files(&dir.join("three")), Vec::<String>::new());
+    assert_eq!(String::from_utf8_lossy(&output.stderr).lines().count(), 3);
+}
+
+#[cfg(unix)]
+#[test]
+fn pack_skips_links_leaving_the_directory() {
+    use std::os::unix::fs::symlink;
+
+    let dir = TempDir::new();
+    let input = dir.write_files("in");
+    fs::write(dir.join("secret.txt"), "outside").unwrap();
+    symlink(dir.join("secret.txt"), input.join("outside.txt")).unwrap();
+    symlink(input.join("empty.txt"), input.join("inside.txt")).unwrap();
+
+    let path = dir.join("Game.rgssad");
+    let output = rgssad()
+        .arg("pack")
+        .arg(&input)
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("link points outside the directory"));
+    let archive = RGSSArchive::from_bytes(&fs::read(&path).unwrap()).unwrap();
+    assert!(archive.find("outside.txt").is_none());
+    assert!(archive.find("inside.txt").is_some());
+
+    let output = rgssad()
+        .args(["pack", "--no-follow-links"])
+        .arg(&input)
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let archive = RGSSArchive::from_bytes(&fs::read(&path).unwrap()).unwrap();
+    assert_eq!(archive.len(), FILES.len());
+}
+
+#[test]
+fn diff_lists_changed_removed_and_added_entries() {
+    let dir = TempDir::new();
+    let a = dir.write_sample("a.rgssad", 1);
+    let b = dir.write_archive(
+        "b.rgss3a",
+        3,
+        &[
+            ("new.txt", b"new"),
+            ("Graphics/Titles/title.png", FILES[1].1),
+            ("Data/Map001.rxdata", b"changed"),
+        ],
+    );
+    let output = rgssad().arg("diff").arg(&a).arg(&b).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "~ Data/Map001.rxdata\n- empty.txt\n+ new.txt\n"
+    );
+
+    let output = rgssad().arg("diff").arg(&a).arg(&a).output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn unpack_resume() {
+    let dir = TempDir::new();
+    let path = dir.write_sample("Game.rgssad", 1);
+    let out = dir.join("out");
+    assert!(rgssad()
+        .arg("unpack")
+        .arg(&path)
+        .arg(&out)
+        .status()
+        .unwrap()
+        .success());
+    // An interrupted extraction, cut off in the middle of a word.
+    let map = out.join("Data/Map001.rxdata");
+    fs::write(&map, &FILES[0].1[..7]).unwrap();
+    fs::remove_file(out.join("empty.txt")).unwrap();
+
+    let output = rgssad()
+        .args(["unpack", "--resume"])
+        .arg(&path)
+        .arg(&out)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Resuming Data/Map001.rxdata at 4"));
+    for (name, body) in FILES {
+        assert_eq!(fs::read(out.join(name)).unwrap(), *body);
+    }
+}
+
+#[test]
+fn unpack_tar_reads_back() {
+    let dir = TempDir::new();
+    let path = dir.write_sample("Game.rgss3a", 3);
+    let output = rgssad()
+        .args(["unpack", "--tar"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let mut tar = tar::Archive::new(&output.stdout[..]);
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    for entry in tar.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let path = entry.path().unwrap().to_str().unwrap().to_owned();
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => dirs.push(path),
+            _ => {
+                let mut body = Vec::new();
+                entry.read_to_end(&mut body).unwrap();
+                files.push((path, body));
+            }
+        }
+    }
+    assert_eq!(dirs, ["Data", "Graphics", "Graphics/Titles"]);
+    let expected: Vec<_> = FILES
+        .iter()
+        .map(|(name, body)| (name.to_string(), body.to_vec()))
+        .collect();
+    assert_eq!(files, expected);
+}
+
+#[test]
+fn unpack_exclude_wins_over_filters() {
+    let dir = TempDir::new();
+    let path = dir.write_sample("Game.rgssad", 1);
+    let unpack = |out: &str, args: &[&str]| {
+        let status = rgssad()
+            .arg("unpack")
+            .args(args)
+            .arg(&path)
+            .arg(dir.join(out))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        unpacked_files(&dir.join(out))
+    };
+    assert_eq!(
+        unpack("exclude", &["--exclude", "^Data/"]),
+        ["Graphics/Titles/title.png", "empty.txt"]
+    );
+    assert_eq!(
+        unpack("glob", &["--glob", "**/*.*", "--exclude", r"\.png$"]),
+        ["Data/Map001.rxdata", "empty.txt"]
+    );
+    // An entry matched by both the include filter and --exclude is left out.
+    let status = rgssad()
+        .args(["unpack", "--exclude", "title"])
+        .arg(&path)
+        .arg(dir.join("regex"))
+        .arg("Graphics")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(unpacked_files(&dir.join("regex")), Vec::<String>::new());
+}
+
+#[test]
+fn info_aggregates_by_extension() {
+    let dir = TempDir::new();
+    let path = dir.write_archive(
+        "Game.rgssad",
+        1,
+        &[
+            ("a.PNG", b"12345"),
+            ("b.png", b"123"),
+            ("Data/c.rxdata", b"1"),
+            ("README", b"12"),
+        ],
+    );
+    let output = rgssad().arg("info").arg(&path).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines[..3], ["Version: 1", "Entries: 4", "Size:    11"]);
+    assert_eq!(
+        lines[4..],
+        [
+            "Extension    Count        Bytes",
+            "(none)           1            2",
+            ".png             2            8",
+            ".rxdata          1            1",
+        ]
+    );
+}
+
+/// Runs `command` with `input` on stdin and returns its output.
+fn run_with_stdin(command: &mut Command, input: &[u8]) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn list_reads_stdin() {
+    for version in 1..=3 {
+        let (archive, data) = sample(version);
+        let output = run_with_stdin(rgssad().args(["list", "-"]), &data);
+        assert!(output.status.success());
+        let expected: String = archive
+            .entries
+            .iter()
+            .map(|entry| format!("{}\n", entry))
+            .collect();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    }
+}
+
+#[test]
+fn pack_stores_names_with_backslashes() {
+    let dir = TempDir::new();
+    let input = dir.write_files("in");
+    let path = dir.join("Game.rgss3a");
+    assert!(rgssad()
+        .arg("pack")
+        .arg(&input)
+        .arg(&path)
+        .status()
+        .unwrap()
+        .success());
+    let data = fs::read(&path).unwrap();
+    let archive = RGSSArchive::from_bytes(&data).unwrap();
+    assert_eq!(archive.entries[0].name, "Data/Map001.rxdata");
+
+    // The first record of the v3 table, decrypted by hand.
+    let word = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+    let xor = rgss3a_xor(word(8)).to_le_bytes();
+    let name_len = (word(24) ^ u32::from_le_bytes(xor)) as usize;
+    let stored: Vec<u8> = data[28..28 + name_len]
+        .iter()
+        .enumerate()
+        .map(|(i, b)| b ^ xor[i % 4])
+        .collect();
+    assert_eq!(stored, b"Data\\Map001.rxdata");
+}
+
+// Most other platforms refuse to create such a name in the first place.
+#[cfg(target_os = "linux")]
+#[test]
+fn pack_reports_non_utf8_file_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = TempDir::new();
+    let input = dir.write_files("in");
+    fs::write(input.join(OsStr::from_bytes(b"bad\xff.txt")), "").unwrap();
+    let output = rgssad()
+        .arg("pack")
+        .arg(&input)
+        .arg(dir.join("Game.rgssad"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("File name is not valid UTF-8"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("bad\u{fffd}.txt"), "{}", stderr);
+}
+
+#[test]
+fn unpack_filter_from() {
+    let dir = TempDir::new();
+    let path = dir.write_sample("Game.rgssad", 1);
+    let list = dir.join("names.txt");
+    fs::write(&list, "Data\\Map001.rxdata\n\nmissing.txt\nempty.txt\n").unwrap();
+    let output = rgssad()
+        .args(["unpack", "--quiet", "--filter-from"])
+        .arg(&list)
+        .arg(&path)
+        .arg(dir.join("out"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        unpacked_files(&dir.join("out")),
+        ["Data/Map001.rxdata", "empty.txt"]
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Not in the archive: missing.txt\n"
+    );
+}
+
+#[test]
+fn repack_keep_offsets_moves_only_changed_files() {
+    let dir = TempDir::new();
+    let template = dir.write_sample("Game.rgss3a", 3);
+    let input = dir.write_files("in");
+    fs::write(
+        input.join("Data/Map001.rxdata"),
+        b"a longer map than before",
+    )
+    .unwrap();
+    let output = dir.join("out.rgss3a");
+
+    let status = rgssad()
+        .args(["repack", "--keep-offsets", "--quiet"])
+        .arg(&input)
+        .arg(&output)
+        .arg(&template)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let before = RGSSArchive::from_bytes(&fs::read(&template).unwrap()).unwrap();
+    let data = fs::read(&output).unwrap();
+    let after = RGSSArchive::from_bytes(&data).unwrap();
+    for i in 1..before.len() {
+        assert_eq!(after.entries()[i].offset, before.entries()[i].offset);
+    }
+    assert_eq!(after.entries()[0].offset as u64, before.data_end());
+    assert_eq!(
+        after.entries()[0].extract_to_vec(&data).unwrap(),
+        b"a longer map than before"
+    );
+}
+
+#[test]
+fn quiet_and_verbose_set_what_unpack_prints() {
+    let dir = TempDir::new();
+    let archive = dir.write_sample("Game.rgssad", 1);
+    let unpack = |flag: Option<&str>, out: &str| {
+        let output = rgssad()
+            .arg("unpack")
+            .args(flag)
+            .arg(&archive)
+            .arg(dir.join(out))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    assert_eq!(unpack(Some("--quiet"), "quiet"), "");
+    let normal = unpack(None, "normal");
+    for (name, _) in FILES {
+        assert!(
+            normal.contains(&format!("Unpacking {} 100%\n", name)),
+            "{}",
+            normal
+        );
+    }
+    assert!(!normal.contains("offset"), "{}", normal);
+    let verbose = unpack(Some("--verbose"), "verbose");
+    assert!(
+        verbose.contains("Data/Map001.rxdata is at offset "),
+        "{}",
+        verbose
+    );
+    assert!(verbose.contains(" 100% (22 bytes)\n"), "{}", verbose);
+    assert_eq!(
+        unpacked_files(&dir.join("quiet")),
+        unpacked_files(&dir.join("verbose"))
+    );
+}
+
+#[test]
+fn list_prints_entries_with_display() {
+    let dir = TempDir::new();
+    let (archive, data) = sample(3);
+    let path = dir.join("Game.rgss3a");
+    fs::write(&path, data).unwrap();
+
+    let output = rgssad().arg("list").arg(&path).output().unwrap();
+    assert!(output.status.success());
+    let expected: String = archive
+        .entries()
+        .iter()
+        .map(|entry| format!("{}\n", entry))
+        .collect();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+#[test]
+fn pack_find_duplicates_compares_contents() {
+    let dir = TempDir::new();
+    let input = dir.join("in");
+    fs::create_dir_all(input.join("b")).unwrap();
+    fs::write(input.join("a.txt"), b"same body").unwrap();
+    fs::write(input.join("b/copy.txt"), b"same body").unwrap();
+    fs::write(input.join("b/other.txt"), b"same size").unwrap();
+    fs::write(input.join("c.txt"), b"same body").unwrap();
+
+    let output = rgssad()
+        .args(["pack", "--quiet", "--find-duplicates"])
+        .arg(&input)
+        .arg(dir.join("out.rgssad"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr,
+        "Duplicate content: a.txt, b/copy.txt, c.txt\n18 bytes in duplicate copies\n"
+    );
+}
+
+#[test]
+fn manifest_rebuilds_identical_archives() {
+    for version in 1..=3 {
+        let dir = TempDir::new();
+        let (_, data) = sample(version);
+        let archive = dir.join("Game.rgssad");
+        fs::write(&archive, &data).unwrap();
+        let manifest = dir.join("manifest.json");
+        let out = dir.join("out");
+
+        let status = rgssad()
+            .args(["unpack", "--quiet", "--manifest"])
+            .arg(&manifest)
+            .arg(&archive)
+            .arg(&out)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let status = rgssad()
+            .args(["pack", "--quiet", "--manifest"])
+            .arg(&manifest)
+            .arg(&out)
+            .arg(dir.join("rebuilt.rgssad"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(
+            fs::read(dir.join("rebuilt.rgssad")).unwrap(),
+            data,
+            "v{}",
+            version
+        );
+    }
+}
+
+#[test]
+fn unpack_size_range() {
+    let dir = TempDir::new();
+    let (small, medium, large) = (vec![1; 10], vec![2; 2000], vec![3; 3 << 20]);
+    let archive = dir.write_archive(
+        "Game.rgssad",
+        1,
+        &[
+            ("small.txt", &small),
+            ("medium.bin", &medium),
+            ("large.bin", &large),
+            ("Data/medium.txt", &medium),
+        ],
+    );
+    let unpack_filtered = |args: &[&str], out: &str, filter: Option<&str>| {
+        let status = rgssad()
+            .args(["unpack", "--quiet"])
+            .args(args)
+            .arg(&archive)
+            .arg(dir.join(out))
+            .args(filter)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        unpacked_files(&dir.join(out))
+    };
+    let unpack = |args: &[&str], out: &str| unpack_filtered(args, out, None);
+
+    assert_eq!(
+        unpack(&["--min-size", "1k", "--max-size", "2M"], "a"),
+        ["Data/medium.txt", "medium.bin"]
+    );
+    assert_eq!(unpack(&["--max-size", "10"], "b"), ["small.txt"]);
+    assert_eq!(unpack(&["--min-size", "2000"], "c").len(), 3);
+    assert_eq!(
+        unpack(&["--min-size", "1K", "--glob", "*.bin"], "d"),
+        ["large.bin", "medium.bin"]
+    );
+    assert_eq!(
+        unpack_filtered(&["--max-size", "1G"], "e", Some("Data/.*")),
+        ["Data/medium.txt"]
+    );
+    assert_eq!(unpack(&["--min-size", "4M"], "f"), Vec::<String>::new());
+    assert_eq!(fs::read(dir.join("a/medium.bin")).unwrap(), medium);
+
+    let output = rgssad()
+        .args(["unpack", "--min-size", "1X"])
+        .arg(&archive)
+        .arg(dir.join("g"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid size: 1X"));
+}
+
+#[test]
+fn repack_add_new_and_allow_missing() {
+    let dir = TempDir::new();
+    let template = dir.write_sample("Game.rgssad", 1);
+    let input = dir.write_files("in");
+    fs::remove_file(input.join("Data/Map001.rxdata")).unwrap();
+    fs::write(input.join("Data/Map002.rxdata"), b"new map").unwrap();
+    let repack = |flags: &[&str], out: &str| {
+        let output = rgssad()
+            .arg("repack")
+            .args(flags)
+            .arg(&input)
+            .arg(dir.join(out))
+            .arg(&template)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("Dropping Data/Map001.rxdata: file is missing"),
+            "{}",
+            stderr
+        );
+        let data = fs::read(dir.join(out)).unwrap();
+        let archive = RGSSArchive::from_bytes(&data).unwrap();
+        archive
+            .entries()
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.extract_to_vec(&data).unwrap()))
+            .collect::<Vec<_>>()
+    };
+
+    let synced = repack(&["--allow-missing", "--add-new"], "synced.rgssad");
+    let names: Vec<_> = synced.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "Graphics/Titles/title.png",
+            "empty.txt",
+            "Data/Map002.rxdata"
+        ]
+    );
+    assert_eq!(synced[2].1, b"new map");
+    assert_eq!(synced[0].1, FILES[1].1);
+
+    let kept = repack(&["--allow-missing"], "kept.rgssad");
+    assert_eq!(kept, synced[..2]);
+}
+
+#[test]
+fn decrypt_matches_archive_bodies() {
+    let (archive, data) = sample(3);
+    let entry = &archive.entries()[0];
+    let body = &data[entry.offset as usize..][..entry.size as usize];
+    let decrypt = |magic: String, size: String, input: &[u8]| {
+        run_with_stdin(
+            rgssad().args(["decrypt", "--magic", &magic, "--size", &size]),
+            input,
+        )
+    };
+
+    for magic in [entry.magic.to_string(), format!("0x{:x}", entry.magic)] {
+        let output = decrypt(magic, entry.size.to_string(), body);
+        assert!(output.status.success());
+        assert_eq!(output.stdout, FILES[0].1);
+    }
+    // The cipher is its own inverse, and input past --size is left unread.
+    let output = decrypt(
+        entry.magic.to_string(),
+        entry.size.to_string(),
+        &[FILES[0].1, b"extra"].concat(),
+    );
+    assert_eq!(output.stdout, body);
+
+    let output = decrypt(entry.magic.to_string(), "1K".to_owned(), body);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Input ended early: 22 of 1024 bytes"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn unpack_restores_saved_timestamps() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    let dir = TempDir::new();
+    let input = dir.write_files("in");
+    let time = |i: u64| UNIX_EPOCH + Duration::new(1_500_000_000 + i * 1000, 123_456_789);
+    for (i, (name, _)) in FILES.iter().enumerate() {
+        let file = fs::File::options()
+            .write(true)
+            .open(input.join(name))
+            .unwrap();
+        file.set_modified(time(i as u64)).unwrap();
+    }
+    let timestamps = dir.join("timestamps.json");
+    let status = rgssad()
+        .args(["pack", "--quiet", "--save-timestamps"])
+        .arg(&timestamps)
+        .arg(&input)
+        .arg(dir.join("Game.rgssad"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let unpack = |flags: &[&str], out: &str| {
+        let status = rgssad()
+            .args(["unpack", "--quiet"])
+            .args(flags)
+            .arg(dir.join("Game.rgssad"))
+            .arg(dir.join(out))
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    unpack(
+        &["--timestamps-from", timestamps.to_str().unwrap()],
+        "restored",
+    );
+    unpack(&[], "fresh");
+    let modified = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+    let before = SystemTime::now() - Duration::from_secs(3600);
+    for (i, (name, _)) in FILES.iter().enumerate() {
+        assert_eq!(modified(&dir.join("restored").join(name)), time(i as u64));
+        assert!(modified(&dir.join("fresh").join(name)) > before);
+    }
+}
+
+#[test]
+fn skip_empty_leaves_out_empty_files() {
+    let dir = TempDir::new();
+    let input = dir.write_files("in");
+    let status = rgssad()
+        .args(["pack", "--quiet", "--skip-empty"])
+        .arg(&input)
+        .arg(dir.join("packed.rgss3a"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let packed = RGSSArchive::from_bytes(&fs::read(dir.join("packed.rgss3a")).unwrap()).unwrap();
+    assert_eq!(packed.len(), FILES.len() - 1);
+    assert!(packed.find("empty.txt").is_none());
+
+    let archive = dir.write_sample("Game.rgss3a", 3);
+    for (flags, out) in [(&["--skip-empty"][..], "skipped"), (&[][..], "all")] {
+        let status = rgssad()
+            .args(["unpack", "--quiet"])
+            .args(flags)
+            .arg(&archive)
+            .arg(dir.join(out))
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+    assert_eq!(
+        unpacked_files(&dir.join("skipped")),
+        ["Data/Map001.rxdata", "Graphics/Titles/title.png"]
+    );
+    assert_eq!(unpacked_files(&dir.join("all")).len(), FILES.len());
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn unpack_compress_zstd_round_trips() {
+    let dir = TempDir::new();
+    let archive = dir.write_sample("Game.rgss3a", 3);
+    let status = rgssad()
+        .args(["unpack", "--quiet", "--compress", "zstd"])
+        .arg(&archive)
+        .arg(dir.join("out"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let mut expected: Vec<_> = FILES
+        .iter()
+        .map(|(name, _)| format!("{}.zst", name))
+        .collect();
+    expected.sort();
+    assert_eq!(unpacked_files(&dir.join("out")), expected);
+    for (name, body) in FILES {
+        let compressed = fs::read(dir.join("out").join(format!("{}.zst", name))).unwrap();
+        assert_eq!(zstd::decode_all(&compressed[..]).unwrap(), *body);
+    }
+}
+
+#[test]
+fn unpack_refuses_names_with_nul() {
+    let dir = TempDir::new();
+    let mut archive = RGSSArchive {
+        version: 1,
+        ..RGSSArchive::default()
+    };
+    for name in ["Data/Map001.rxdata", "Data/Map\0.rxdata"] {
+        archive.entries.push(RGSSArchiveEntry {
+            name: name.to_owned(),
+            ..RGSSArchiveEntry::default()
+        });
+    }
+    let path = dir.join("nul.rgssad");
+    fs::write(&path, archive.write_to_vec(|_| b"body".to_vec()).unwrap()).unwrap();
+
+    let output = rgssad()
+        .arg("unpack")
+        .arg(&path)
+        .arg(dir.join("out"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: Entry name is not a safe relative path: Data/Map\\0.rxdata\n"
+    );
+    // Names are checked before anything is extracted.
+    assert_eq!(unpacked_files(&dir.join("out")), Vec::<String>::new());
+}
+
+#[test]
+fn repack_checksums_reuses_unchanged_bodies() {
+    for version in [1, 3] {
+        let dir = TempDir::new();
+        let template = dir.write_sample("Game.rgssad", version);
+        let input = dir.write_files("in");
+        let output = rgssad().arg("checksum").arg(&template).output().unwrap();
+        assert!(output.status.success());
+        fs::write(dir.join("checksums.txt"), output.stdout).unwrap();
+        let repack = |checksums: bool, out: &str| {
+            let mut command = rgssad();
+            command.args(["repack", "--verbose"]);
+            if checksums {
+                command.arg("--checksums").arg(dir.join("checksums.txt"));
+            }
+            let output = command
+                .arg(&input)
+                .arg(dir.join(out))
+                .arg(&template)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            (
+                fs::read(dir.join(out)).unwrap(),
+                String::from_utf8(output.stderr).unwrap(),
+            )
+        };
+
+        let (data, log) = repack(true, "same.rgssad");
+        assert_eq!(data, fs::read(&template).unwrap());
+        for (name, _) in FILES {
+            assert!(log.contains(&format!("Reusing {}\n", name)), "{}", log);
+        }
+
+        fs::write(input.join("Data/Map001.rxdata"), b"changed, same length!!").unwrap();
+        let (reused, log) = repack(true, "reused.rgssad");
+        assert!(log.contains("Packing Data/Map001.rxdata\n"), "{}", log);
+        assert!(log.contains("Reusing empty.txt\n"), "{}", log);
+        let (packed, _) = repack(false, "packed.rgssad");
+        assert_eq!(reused, packed, "v{}", version);
+        assert_ne!(reused, data);
+    }
+}
+
+#[test]
+fn pack_rejects_archives_over_4_gib_before_creating_the_output() {
+    let dir = TempDir::new();
+    let input = dir.join("in");
+    fs::create_dir_all(&input).unwrap();
+    // Each file fits on its own, but not both together. Sparse on most file
+    // systems, so this takes no real space.
+    for (name, len) in [("a.bin", 5u64 << 29), ("b.bin", 3 << 30)] {
+        fs::File::create(input.join(name))
+            .unwrap()
+            .set_len(len)
+            .unwrap();
+    }
+
+    let out = dir.join("out.rgssad");
+    let output = rgssad()
+        .args(["pack", "--quiet"])
+        .arg(&input)
+        .arg(&out)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: Archive exceeds the 4 GiB offset limit at entry: b.bin\n"
+    );
+    assert!(!out.exists());
+}
+
+#[test]
+fn pack_rejects_files_over_4_gib() {
+    let dir = TempDir::new();
+    let input = dir.join("in");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("small.txt"), b"small").unwrap();
+    // Sparse on most file systems, so this takes no real space.
+    fs::File::create(input.join("huge.bin"))
+        .unwrap()
+        .set_len(4 << 30)
+        .unwrap();
+
+    let output = rgssad()
+        .args(["pack", "--quiet"])
+        .arg(&input)
+        .arg(dir.join("out.rgssad"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("File exceeds the 4 GiB entry size limit"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("huge.bin"), "{}", stderr);
+    assert!(!dir.join("out.rgssad").exists());
+}
+
+#[test]
+fn list_json_describes_every_entry() {
+    let dir = TempDir::new();
+    let (archive, data) = sample(3);
+    let path = dir.join("Game.rgss3a");
+    fs::write(&path, data).unwrap();
+
+    let output = rgssad()
+        .args(["list", "--json"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let listed = listed.as_array().unwrap();
+    assert_eq!(listed.len(), archive.len());
+    for (json, entry) in listed.iter().zip(archive.entries()) {
+        assert_eq!(json["name"], entry.name.as_str());
+        assert_eq!(json["size"], entry.size);
+        assert_eq!(json["offset"], entry.offset);
+        assert_eq!(json["magic"], entry.magic);
+    }
+}
+
+#[test]
+fn cat_writes_one_entry() {
+    let dir = TempDir::new();
+    let archive = dir.write_sample("Game.rgss3a", 3);
+    for name in ["Data/Map001.rxdata", "Data\\Map001.rxdata"] {
+        for output in [None, Some("-")] {
+            let out = rgssad()
+                .arg("cat")
+                .arg(&archive)
+                .arg(name)
+                .args(output)
+                .output()
+                .unwrap();
+            assert!(out.status.success());
+            assert_eq!(out.stdout, FILES[0].1, "{} {:?}", name, output);
+        }
+    }
+
+    let status = rgssad()
+        .arg("cat")
+        .arg(&archive)
+        .arg("Graphics/Titles/title.png")
+        .arg(dir.join("title.png"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(fs::read(dir.join("title.png")).unwrap(), FILES[1].1);
+
+    let out = rgssad()
+        .arg("cat")
+        .arg(&archive)
+        .arg("Data/Map009.rxdata")
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("No such entry: Data/Map009.rxdata"),
+        "{}",
+        stderr
+    );
+}
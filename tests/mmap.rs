@@ -0,0 +1,34 @@
+#![cfg(feature = "mmap")]
+
+mod common;
+
+use std::fs::File;
+
+use common::{TempDir, FILES};
+use rgssad::RGSSArchive;
+
+#[test]
+fn mmap_matches_file_reads() {
+    for version in 1..=3 {
+        let dir = TempDir::new();
+        let path = dir.write_sample("Game.rgssad", version);
+        let mapped = RGSSArchive::open_mmap(&path).unwrap();
+        let mut file = File::open(&path).unwrap();
+        let archive = RGSSArchive::open(&mut file).unwrap();
+        assert_eq!(mapped.archive, archive);
+        assert_eq!(mapped.bytes(), std::fs::read(&path).unwrap());
+
+        for entry in &archive.entries {
+            let mut from_map = Vec::new();
+            mapped.read(&mut [0; 8], entry, &mut from_map).unwrap();
+            let mut from_file = Vec::new();
+            entry.read(&mut [0; 8], &mut file, &mut from_file).unwrap();
+            assert_eq!(from_map, from_file);
+            assert_eq!(mapped.extract(&entry.name).unwrap().unwrap(), from_file);
+        }
+        for (name, body) in FILES {
+            assert_eq!(mapped.extract(name).unwrap().unwrap(), *body);
+        }
+        assert!(mapped.extract("missing").is_none());
+    }
+}